@@ -0,0 +1,368 @@
+//! Attitude and heading reference system (AHRS) filters
+//!
+//! Both filters fuse gyroscope, accelerometer and optionally magnetometer
+//! readings into an orientation that maps body coordinates to the earth
+//! frame, the same convention as `kinematics` and `rotate_vector`. The earth
+//! frame has z up and magnetic north in the x-z plane. Gyroscope readings are
+//! in radians per second; accelerometer and magnetometer readings may have
+//! any scale since only their directions are used, and an accelerometer at
+//! rest reads `+z` when level.
+
+use vecmath::traits::Float;
+use vecmath::{vec3_add, vec3_cross, vec3_dot, vec3_normalized, vec3_scale, vec3_square_len};
+
+use {Quaternion, UnitQuaternion, conj, mul, normalize, rotate_vector};
+use kinematics::{self, q_dot, Frame};
+
+
+/// Gravity direction as read by a level accelerometer, in the earth frame
+#[inline(always)]
+fn up<T>() -> [T; 3]
+    where T: Float
+{
+    [T::zero(), T::zero(), T::one()]
+}
+
+/// Normalizes `v`, returning `None` for a zero reading
+#[inline(always)]
+fn direction<T>(v: [T; 3]) -> Option<[T; 3]>
+    where T: Float
+{
+    if vec3_square_len(v) > T::zero() {
+        Some(vec3_normalized(v))
+    } else {
+        None
+    }
+}
+
+/// Earth frame magnetic reference implied by a body frame reading: the
+/// reading rotated to the earth frame, then turned about z into the x-z plane
+#[inline(always)]
+fn magnetic_reference<T>(q: UnitQuaternion<T>, m: [T; 3]) -> [T; 3]
+    where T: Float
+{
+    let h = rotate_vector(q, m);
+    [(h[0] * h[0] + h[1] * h[1]).sqrt(), T::zero(), h[2]]
+}
+
+/// Gradient `J^T f` of `f = q* d q - s` as in Madgwick's report, for earth
+/// frame reference `d` and body frame measurement `s`
+///
+/// The report's Jacobian simplifies the diagonal of the rotation matrix with
+/// `|q| = 1`, which adds `-2 (d . f) q` to the true gradient `-2 d q f`.
+#[inline(always)]
+fn gradient<T>(q: Quaternion<T>, d: [T; 3], s: [T; 3]) -> Quaternion<T>
+    where T: Float
+{
+    let zero = T::zero();
+    let d_body = rotate_vector(UnitQuaternion::new_unchecked(conj(q)), d);
+    let f = vec3_add(d_body, vec3_scale(s, -T::one()));
+    let radial = q * vec3_dot(d, f);
+    let d: Quaternion<T> = (zero, d).into();
+    let f: Quaternion<T> = (zero, f).into();
+    (mul(mul(d, q), f) + radial) * T::from_f64(-2.0)
+}
+
+/// Madgwick's gradient descent orientation filter
+///
+/// See Madgwick, "An efficient orientation filter for inertial and
+/// inertial/magnetic sensor arrays" (2010).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Madgwick<T> {
+    /// Gain of the gradient descent step, in radians per second
+    ///
+    /// Larger values trust the accelerometer and magnetometer more.
+    pub beta: T,
+    q: UnitQuaternion<T>,
+}
+
+impl<T> Madgwick<T>
+    where T: Float
+{
+    /// Constructs a filter starting at the identity orientation
+    pub fn new(beta: T) -> Madgwick<T> {
+        Madgwick::with_orientation(beta, UnitQuaternion::identity())
+    }
+
+    /// Constructs a filter starting at orientation `q`
+    pub fn with_orientation(beta: T, q: UnitQuaternion<T>) -> Madgwick<T> {
+        Madgwick { beta, q }
+    }
+
+    /// Current orientation estimate
+    pub fn orientation(&self) -> UnitQuaternion<T> {
+        self.q
+    }
+
+    /// Updates from gyroscope and accelerometer readings (6-DoF)
+    ///
+    /// A zero accelerometer reading leaves only the gyroscope integration.
+    pub fn update_imu(&mut self, gyro: [T; 3], accel: [T; 3], dt: T) {
+        let grad = direction(accel).map(|a| gradient(*self.q, up(), a));
+        self.step(gyro, grad, dt);
+    }
+
+    /// Updates from gyroscope, accelerometer and magnetometer readings (9-DoF)
+    ///
+    /// Falls back to `update_imu` when the magnetometer reading is zero.
+    pub fn update(&mut self, gyro: [T; 3], accel: [T; 3], mag: [T; 3], dt: T) {
+        let (a, m) = match (direction(accel), direction(mag)) {
+            (Some(a), Some(m)) => (a, m),
+            _ => return self.update_imu(gyro, accel, dt),
+        };
+        let q = *self.q;
+        let b = magnetic_reference(self.q, m);
+        let grad = gradient(q, up(), a) + gradient(q, b, m);
+        self.step(gyro, Some(grad), dt);
+    }
+
+    fn step(&mut self, gyro: [T; 3], grad: Option<Quaternion<T>>, dt: T) {
+        let q = *self.q;
+        let mut dq = q_dot(q, gyro, Frame::Body);
+        if let Some(grad) = grad {
+            dq -= normalize(grad) * self.beta;
+        }
+        self.q = UnitQuaternion::new_unchecked(normalize(q + dq * dt));
+    }
+}
+
+/// Mahony's nonlinear complementary filter
+///
+/// See Mahony, Hamel and Pflimlin, "Nonlinear Complementary Filters on the
+/// Special Orthogonal Group" (2008). As in the reference implementation, the
+/// corrected rate is integrated with a forward Euler step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mahony<T> {
+    /// Proportional gain on the direction error
+    pub kp: T,
+    /// Integral gain on the direction error, which estimates gyroscope bias
+    pub ki: T,
+    q: UnitQuaternion<T>,
+    integral: [T; 3],
+}
+
+impl<T> Mahony<T>
+    where T: Float
+{
+    /// Constructs a filter starting at the identity orientation
+    pub fn new(kp: T, ki: T) -> Mahony<T> {
+        Mahony::with_orientation(kp, ki, UnitQuaternion::identity())
+    }
+
+    /// Constructs a filter starting at orientation `q`
+    pub fn with_orientation(kp: T, ki: T, q: UnitQuaternion<T>) -> Mahony<T> {
+        Mahony { kp, ki, q, integral: [T::zero(); 3] }
+    }
+
+    /// Current orientation estimate
+    pub fn orientation(&self) -> UnitQuaternion<T> {
+        self.q
+    }
+
+    /// Accumulated integral correction, the negated gyroscope bias estimate
+    pub fn integral(&self) -> [T; 3] {
+        self.integral
+    }
+
+    /// Updates from gyroscope and accelerometer readings (6-DoF)
+    ///
+    /// A zero accelerometer reading leaves only the gyroscope integration.
+    pub fn update_imu(&mut self, gyro: [T; 3], accel: [T; 3], dt: T) {
+        let error = direction(accel).map(|a| {
+            let v = rotate_vector(self.q.inverse(), up());
+            vec3_cross(a, v)
+        });
+        self.step(gyro, error, dt);
+    }
+
+    /// Updates from gyroscope, accelerometer and magnetometer readings (9-DoF)
+    ///
+    /// Falls back to `update_imu` when the magnetometer reading is zero.
+    pub fn update(&mut self, gyro: [T; 3], accel: [T; 3], mag: [T; 3], dt: T) {
+        let (a, m) = match (direction(accel), direction(mag)) {
+            (Some(a), Some(m)) => (a, m),
+            _ => return self.update_imu(gyro, accel, dt),
+        };
+        let inv = self.q.inverse();
+        let v = rotate_vector(inv, up());
+        let w = rotate_vector(inv, magnetic_reference(self.q, m));
+        self.step(gyro, Some(vec3_add(vec3_cross(a, v), vec3_cross(m, w))), dt);
+    }
+
+    fn step(&mut self, gyro: [T; 3], error: Option<[T; 3]>, dt: T) {
+        let mut omega = gyro;
+        if let Some(e) = error {
+            if self.ki > T::zero() {
+                self.integral = vec3_add(self.integral, vec3_scale(e, self.ki * dt));
+            }
+            omega = vec3_add(omega, vec3_add(vec3_scale(e, self.kp), self.integral));
+        }
+        self.q = kinematics::integrate_euler(self.q, omega, dt, Frame::Body);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{angle_between, assert_same_rotation, rot};
+
+    /// Gyroscope, accelerometer and magnetometer readings, cycled through by
+    /// the reference tests
+    const INPUTS: [[[f64; 3]; 3]; 8] = [
+        [[0.12, -0.05, 0.30], [0.4, -0.3, 9.7], [21.0, -3.0, -42.0]],
+        [[0.10, -0.02, 0.28], [0.6, -0.1, 9.8], [20.5, -2.0, -41.5]],
+        [[0.05, 0.04, 0.25], [0.7, 0.2, 9.9], [19.8, -0.5, -41.8]],
+        [[-0.02, 0.08, 0.20], [0.5, 0.4, 9.8], [19.0, 1.0, -42.2]],
+        [[-0.08, 0.10, 0.12], [0.2, 0.6, 9.7], [18.7, 2.4, -42.6]],
+        [[-0.11, 0.07, 0.05], [-0.1, 0.5, 9.8], [18.9, 3.1, -42.1]],
+        [[-0.06, 0.01, -0.02], [-0.3, 0.2, 9.9], [19.6, 2.2, -41.7]],
+        [[0.03, -0.04, 0.10], [0.0, -0.1, 9.8], [20.4, 0.3, -41.9]],
+    ];
+
+    /// Orientations after every 50 steps of 0.01 s through `INPUTS`, from C
+    /// reference implementations run in double precision: the update of
+    /// Madgwick's report with beta 0.1 and Mahony's `MahonyAHRS.c` with kp 0.5
+    /// and ki 0.1, each starting at the identity
+    const MADGWICK_IMU: [[f64; 4]; 4] = [
+        [0.9991011932604872, 0.006673576648330495, -0.006953383572303881, 0.04127855929033594],
+        [0.9965474272624141, 0.00992531941621958, -0.009420249135998909, 0.08189000035025672],
+        [0.9925607046596266, 0.011464936387692248, -0.007933925476396533, 0.12094980622450908],
+        [0.9871345852394243, 0.010700210425824726, -0.006522982679587112, 0.15939970770987794],
+    ];
+    const MADGWICK_MARG: [[f64; 4]; 4] = [
+        [0.9995076523836796, -0.0018290878898158396, -0.010003971297329573, 0.02968211283313533],
+        [0.9984705480815039, -0.005304530149155461, -0.013905786383646205, 0.053245240902546905],
+        [0.9973198381041561, -0.010628315883139717, -0.01321748674159562, 0.07117216780191266],
+        [0.9960698465487483, -0.015221628871148606, -0.012241988777896172, 0.0863903728526733],
+    ];
+    const MAHONY_IMU: [[f64; 4]; 4] = [
+        [0.9991384998089211, 0.0036316607941830753, 0.001609373524762352, 0.04130955284571041],
+        [0.9966141587438541, 0.006051972039334761, 0.003329310597817475, 0.08192989635449503],
+        [0.9926096991251248, 0.006987251613457915, 0.004840421141382596, 0.1210526077405745],
+        [0.9871435179334166, 0.008178707615393464, 0.004997309976246792, 0.15954877196889236],
+    ];
+    const MAHONY_MARG: [[f64; 4]; 4] = [
+        [0.9992001555966279, 0.0008376280475989625, 0.0013611645249250895, 0.03995615930072085],
+        [0.9969968059448865, -0.0023331367473437407, 0.00301134276986883, 0.07734893162374605],
+        [0.9937011444834652, -0.00869010759722046, 0.004668740035484939, 0.11162759671640783],
+        [0.9894434987955831, -0.01540728331020427, 0.005280075672683116, 0.1440010385832146],
+    ];
+
+    /// Runs `update` over 200 steps of `INPUTS`, checking the orientation
+    /// against `expected` every 50 steps
+    fn check_reference<F>(mut update: F, expected: &[[f64; 4]; 4])
+        where F: FnMut(&[[f64; 3]; 3]) -> UnitQuaternion<f64>
+    {
+        for k in 0..200 {
+            let q = update(&INPUTS[k % INPUTS.len()]);
+            if (k + 1) % 50 == 0 {
+                let e = expected[k / 50];
+                let e = UnitQuaternion::new_unchecked(Quaternion::new(e[0], e[1], e[2], e[3]));
+                let err = angle_between(q, e);
+                assert!(err < 1e-12, "step {}: {:?} is {} rad from {:?}", k + 1, q, err, e);
+            }
+        }
+    }
+
+    /// Ideal sensor readings for orientation `q`
+    fn readings(q: UnitQuaternion<f64>) -> ([f64; 3], [f64; 3]) {
+        let dip = 1.0f64;
+        let field = [dip.cos() * 48.0, 0.0, -dip.sin() * 48.0];
+        let inv = q.inverse();
+        (rotate_vector(inv, [0.0, 0.0, 9.81]), rotate_vector(inv, field))
+    }
+
+    fn tilt_error(q: UnitQuaternion<f64>, truth: UnitQuaternion<f64>) -> f64 {
+        let a = rotate_vector(q.inverse(), [0.0, 0.0, 1.0]);
+        let b = rotate_vector(truth.inverse(), [0.0, 0.0, 1.0]);
+        vec3_square_len([a[0] - b[0], a[1] - b[1], a[2] - b[2]]).sqrt()
+    }
+
+    #[test]
+    fn test_madgwick_reference() {
+        let dt = 0.01;
+        let mut filter = Madgwick::new(0.1);
+        check_reference(|r| { filter.update_imu(r[0], r[1], dt); filter.orientation() },
+                        &MADGWICK_IMU);
+        let mut filter = Madgwick::new(0.1);
+        check_reference(|r| { filter.update(r[0], r[1], r[2], dt); filter.orientation() },
+                        &MADGWICK_MARG);
+    }
+
+    #[test]
+    fn test_mahony_reference() {
+        let dt = 0.01;
+        let mut filter = Mahony::new(0.5, 0.1);
+        check_reference(|r| { filter.update_imu(r[0], r[1], dt); filter.orientation() },
+                        &MAHONY_IMU);
+        let mut filter = Mahony::new(0.5, 0.1);
+        check_reference(|r| { filter.update(r[0], r[1], r[2], dt); filter.orientation() },
+                        &MAHONY_MARG);
+    }
+
+    #[test]
+    fn test_madgwick_imu_levels() {
+        let truth = rot([1.0, 0.0, 0.0], 0.3);
+        let (accel, _) = readings(truth);
+        let mut filter = Madgwick::new(0.5);
+        for _ in 0..2000 {
+            filter.update_imu([0.0; 3], accel, 0.01);
+        }
+        // the normalized gradient step keeps chattering, and a step of beta * dt
+        // in the quaternion turns by up to 2 * beta * dt
+        assert!(tilt_error(filter.orientation(), truth) < 2.0 * 0.5 * 0.01);
+    }
+
+    #[test]
+    fn test_madgwick_marg_converges() {
+        let truth = rot([0.2, -0.5, 1.0], 1.2);
+        let (accel, mag) = readings(truth);
+        let mut filter = Madgwick::new(0.5);
+        for _ in 0..4000 {
+            filter.update([0.0; 3], accel, mag, 0.01);
+        }
+        assert_same_rotation(filter.orientation(), truth, 2.0 * 0.5 * 0.01);
+    }
+
+    #[test]
+    fn test_madgwick_tracks_rotation() {
+        let omega = [0.3, -0.2, 0.5];
+        let mut truth = rot([0.0, 1.0, 0.0], 0.4);
+        let mut filter = Madgwick::with_orientation(0.05, truth);
+        for _ in 0..1000 {
+            truth = kinematics::integrate(truth, omega, 0.01, Frame::Body);
+            let (accel, mag) = readings(truth);
+            filter.update(omega, accel, mag, 0.01);
+        }
+        // the first order gyroscope integration lags behind the truth
+        assert_same_rotation(filter.orientation(), truth, 0.01);
+    }
+
+    #[test]
+    fn test_mahony_imu_levels() {
+        let truth = rot([0.0, 1.0, 1.0], 0.5);
+        let (accel, _) = readings(truth);
+        let mut filter = Mahony::new(2.0, 0.0);
+        for _ in 0..2000 {
+            filter.update_imu([0.0; 3], accel, 0.01);
+        }
+        assert!(tilt_error(filter.orientation(), truth) < 1e-3);
+    }
+
+    #[test]
+    fn test_mahony_estimates_bias() {
+        let truth = rot([1.0, 0.5, -0.3], 0.9);
+        let (accel, mag) = readings(truth);
+        let bias = [0.02, -0.01, 0.015];
+        let mut filter = Mahony::new(1.0, 0.3);
+        for _ in 0..20000 {
+            filter.update(bias, accel, mag, 0.01);
+        }
+        assert_same_rotation(filter.orientation(), truth, 1e-6);
+        let integral = filter.integral();
+        for i in 0..3 {
+            assert!((integral[i] + bias[i]).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Attitude parameterizations used in spacecraft dynamics
+//!
+//! Modified Rodrigues parameters (MRPs), classical Rodrigues parameters
+//! (Gibbs vectors) and Cayley–Klein parameters, converted to and from the
+//! crate's unit quaternions. Compositions follow the quaternion product, so
+//! `mrp_compose(a, b)` is the MRP of `from_mrp(a) * from_mrp(b)`.
+
+use vecmath::traits::Float;
+use vecmath::{vec3_add, vec3_cross, vec3_dot, vec3_scale, vec3_square_len};
+
+use {Quaternion, UnitQuaternion, mul};
+
+
+/// Modified Rodrigues parameters of a rotation, `v / (1 + w)`
+///
+/// The short rotation set is returned, with norm at most 1.
+#[inline(always)]
+pub fn to_mrp<T>(q: UnitQuaternion<T>) -> [T; 3]
+    where T: Float
+{
+    let q = if q.w < T::zero() { -q.into_inner() } else { q.into_inner() };
+    vec3_scale(q.vector(), T::one() / (T::one() + q.w))
+}
+
+/// Unit quaternion of a set of modified Rodrigues parameters
+#[inline(always)]
+pub fn from_mrp<T>(s: [T; 3]) -> UnitQuaternion<T>
+    where T: Float
+{
+    let one = T::one();
+    let s2 = vec3_square_len(s);
+    let d = one / (one + s2);
+    let q = ((one - s2) * d, vec3_scale(s, (one + one) * d));
+    UnitQuaternion::new_unchecked(q.into())
+}
+
+/// Shadow set `-s / |s|^2`, the same rotation through the other side of the sphere
+///
+/// Returns `None` for zero parameters, whose shadow is at infinity.
+#[inline(always)]
+pub fn mrp_shadow<T>(s: [T; 3]) -> Option<[T; 3]>
+    where T: Float
+{
+    let s2 = vec3_square_len(s);
+    if s2 > T::zero() {
+        Some(vec3_scale(s, -T::one() / s2))
+    } else {
+        None
+    }
+}
+
+/// Switches to the shadow set when the norm exceeds 1
+#[inline(always)]
+pub fn mrp_switch<T>(s: [T; 3]) -> [T; 3]
+    where T: Float
+{
+    if vec3_square_len(s) > T::one() {
+        vec3_scale(s, -T::one() / vec3_square_len(s))
+    } else {
+        s
+    }
+}
+
+/// Composes two sets of modified Rodrigues parameters, `a` then `b` as in `a * b`
+///
+/// The result is switched to the short rotation set. When the composition is
+/// close to a full turn the direct formula is singular, so the product is
+/// taken through quaternions instead.
+pub fn mrp_compose<T>(a: [T; 3], b: [T; 3]) -> [T; 3]
+    where T: Float
+{
+    let one = T::one();
+    let two = one + one;
+    let a2 = vec3_square_len(a);
+    let b2 = vec3_square_len(b);
+
+    let denom = one + a2 * b2 - two * vec3_dot(a, b);
+    if denom < T::from_f64(1e-6) {
+        return to_mrp(from_mrp(a) * from_mrp(b));
+    }
+
+    let num = vec3_add(
+        vec3_add(vec3_scale(b, one - a2), vec3_scale(a, one - b2)),
+        vec3_scale(vec3_cross(a, b), two)
+    );
+    mrp_switch(vec3_scale(num, one / denom))
+}
+
+/// Classical Rodrigues parameters (Gibbs vector) of a rotation, `v / w`
+///
+/// Returns `None` for half turns, where the parameters are infinite.
+#[inline(always)]
+pub fn to_gibbs<T>(q: UnitQuaternion<T>) -> Option<[T; 3]>
+    where T: Float
+{
+    let w = q.w;
+    if w * w > T::from_f64(1e-12) {
+        Some(vec3_scale(q.vector(), T::one() / w))
+    } else {
+        None
+    }
+}
+
+/// Unit quaternion of a Gibbs vector
+#[inline(always)]
+pub fn from_gibbs<T>(g: [T; 3]) -> UnitQuaternion<T>
+    where T: Float
+{
+    let d = T::one() / (T::one() + vec3_square_len(g)).sqrt();
+    UnitQuaternion::new_unchecked((d, vec3_scale(g, d)).into())
+}
+
+/// Composes two Gibbs vectors, `a` then `b` as in `a * b`
+///
+/// Returns `None` when the composition is a half turn.
+#[inline(always)]
+pub fn gibbs_compose<T>(a: [T; 3], b: [T; 3]) -> Option<[T; 3]>
+    where T: Float
+{
+    to_gibbs(from_gibbs(a) * from_gibbs(b))
+}
+
+/// Cayley–Klein parameters, the entries of the SU(2) matrix
+/// `[[alpha, beta], [-conj(beta), conj(alpha)]]`
+///
+/// Complex numbers are stored as `[re, im]`. The quaternion `w + xi + yj + zk`
+/// maps to `alpha = w - iz`, `beta = -y - ix`, which turns the quaternion
+/// product into the matrix product.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CayleyKlein<T> {
+    /// Upper left entry
+    pub alpha: [T; 2],
+    /// Upper right entry
+    pub beta: [T; 2],
+}
+
+impl<T> CayleyKlein<T>
+    where T: Float
+{
+    /// The full 2x2 complex matrix, row major
+    pub fn to_mat2(&self) -> [[[T; 2]; 2]; 2] {
+        let [ar, ai] = self.alpha;
+        let [br, bi] = self.beta;
+        [[[ar, ai], [br, bi]], [[-br, bi], [ar, -ai]]]
+    }
+}
+
+/// Cayley–Klein parameters of a unit quaternion
+#[inline(always)]
+pub fn to_cayley_klein<T>(q: UnitQuaternion<T>) -> CayleyKlein<T>
+    where T: Float
+{
+    CayleyKlein { alpha: [q.w, -q.z], beta: [-q.y, -q.x] }
+}
+
+/// Unit quaternion of a set of Cayley–Klein parameters
+#[inline(always)]
+pub fn from_cayley_klein<T>(ck: CayleyKlein<T>) -> UnitQuaternion<T>
+    where T: Float
+{
+    let q = Quaternion::new(ck.alpha[0], -ck.beta[1], -ck.beta[0], -ck.alpha[1]);
+    UnitQuaternion::new_unchecked(q)
+}
+
+/// Composes two sets of Cayley–Klein parameters, `a` then `b` as in `a * b`
+#[inline(always)]
+pub fn cayley_klein_compose<T>(a: CayleyKlein<T>, b: CayleyKlein<T>) -> CayleyKlein<T>
+    where T: Float
+{
+    let q = mul(*from_cayley_klein(a), *from_cayley_klein(b));
+    to_cayley_klein(UnitQuaternion::new_unchecked(q))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{assert_close, assert_same_rotation, rot};
+
+    static EPSILON: f64 = 0.000001;
+
+    #[test]
+    fn test_mrp_round_trip() {
+        let q = rot([1.0, 2.0, -0.5], 2.0);
+        let s = to_mrp(q);
+        assert!(vec3_square_len(s) <= 1.0);
+        assert_same_rotation(from_mrp(s), q, 1e-12);
+
+        // a rotation past pi comes back on the short set
+        let q = rot([0.0, 0.0, 1.0], 4.0);
+        assert!(vec3_square_len(to_mrp(q)) <= 1.0);
+        assert_same_rotation(from_mrp(to_mrp(q)), q, 1e-12);
+    }
+
+    #[test]
+    fn test_mrp_shadow() {
+        let s = to_mrp(rot([1.0, -1.0, 0.0], 1.0));
+        let shadow = mrp_shadow(s).unwrap();
+        assert!(vec3_square_len(shadow) > 1.0);
+        assert_same_rotation(from_mrp(shadow), from_mrp(s), 1e-12);
+        assert_close(mrp_switch(shadow), s, 1e-12);
+        assert_eq!(mrp_shadow([0.0f64; 3]), None);
+    }
+
+    #[test]
+    fn test_mrp_compose() {
+        let a = rot([1.0, 0.2, 0.0], 1.3);
+        let b = rot([0.0, -1.0, 2.0], 2.4);
+        let c = mrp_compose(to_mrp(a), to_mrp(b));
+        assert_same_rotation(from_mrp(c), a * b, 1e-12);
+        assert!(vec3_square_len(c) <= 1.0);
+
+        // composing to a full turn goes through the singular branch
+        let theta = ::std::f64::consts::PI - 1e-4;
+        let half = to_mrp(rot([0.0, 0.0, 1.0], theta));
+        let c = mrp_compose(half, half);
+        assert_same_rotation(from_mrp(c), rot([0.0, 0.0, 1.0], 2.0 * theta), 1e-12);
+        assert!(vec3_square_len(c) <= 1.0);
+    }
+
+    #[test]
+    fn test_gibbs() {
+        let a = rot([0.3, 0.4, 0.5], 1.1);
+        let b = rot([-1.0, 0.0, 1.0], 0.7);
+        assert_same_rotation(from_gibbs(to_gibbs(a).unwrap()), a, 1e-12);
+        let ab = gibbs_compose(to_gibbs(a).unwrap(), to_gibbs(b).unwrap()).unwrap();
+        assert_same_rotation(from_gibbs(ab), a * b, 1e-12);
+        assert_eq!(to_gibbs(rot([0.0, 1.0, 0.0], ::std::f64::consts::PI)), None);
+    }
+
+    #[test]
+    fn test_cayley_klein() {
+        fn cmul(a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+            [a[0] * b[0] - a[1] * b[1], a[0] * b[1] + a[1] * b[0]]
+        }
+
+        let a = rot([0.3, -0.4, 0.5], 1.7);
+        let b = rot([1.0, 1.0, 0.0], -0.6);
+        assert_same_rotation(from_cayley_klein(to_cayley_klein(a)), a, 1e-12);
+
+        // the map is a homomorphism: the matrix of a * b is the matrix product
+        let ma = to_cayley_klein(a).to_mat2();
+        let mb = to_cayley_klein(b).to_mat2();
+        let mab = to_cayley_klein(a * b).to_mat2();
+        for r in 0..2 {
+            for c in 0..2 {
+                let x = cmul(ma[r][0], mb[0][c]);
+                let y = cmul(ma[r][1], mb[1][c]);
+                assert!((x[0] + y[0] - mab[r][c][0]).abs() < EPSILON);
+                assert!((x[1] + y[1] - mab[r][c][1]).abs() < EPSILON);
+            }
+        }
+
+        let ab = cayley_klein_compose(to_cayley_klein(a), to_cayley_klein(b));
+        assert_same_rotation(from_cayley_klein(ab), a * b, 1e-12);
+    }
+}
@@ -0,0 +1,117 @@
+//! Averaging of orientation samples
+//!
+//! Since `q` and `-q` are the same rotation, a plain component-wise mean is
+//! meaningless for samples that straddle hemispheres. `weighted_average` uses
+//! Markley's method, which is immune to the sign of each sample.
+
+use vecmath::traits::Float;
+
+use {Quaternion, UnitQuaternion, dot, try_normalize};
+use eigen::dominant_eigenvector;
+
+
+/// Rotation minimizing the weighted sum of squared chordal distances to the samples
+///
+/// Following Markley et al., "Averaging Quaternions" (2007), this is the
+/// dominant eigenvector of `sum(w_i * q_i * q_i^T)`. The result is signed to
+/// lie in the hemisphere of the first sample. Returns `None` if there are no
+/// samples or the weights sum to zero. Panics if `weights` and `samples`
+/// differ in length.
+pub fn weighted_average<T>(samples: &[UnitQuaternion<T>], weights: &[T])
+    -> Option<UnitQuaternion<T>>
+    where T: Float
+{
+    assert_eq!(samples.len(), weights.len(), "one weight is needed per sample");
+
+    let zero = T::zero();
+    let mut total = zero;
+    let mut m = [[zero; 4]; 4];
+    for (q, &w) in samples.iter().zip(weights.iter()) {
+        total += w;
+        for r in 0..4 {
+            for c in r..4 {
+                m[r][c] += w * q[r] * q[c];
+            }
+        }
+    }
+    if samples.is_empty() || total <= zero {
+        return None;
+    }
+
+    let (_, q) = dominant_eigenvector(m);
+    let q = if dot(q, *samples[0]) < zero { -q } else { q };
+    UnitQuaternion::new(q)
+}
+
+/// Unweighted `weighted_average`
+pub fn average<T>(samples: &[UnitQuaternion<T>]) -> Option<UnitQuaternion<T>>
+    where T: Float
+{
+    weighted_average(samples, &vec![T::one(); samples.len()])
+}
+
+/// Normalized weighted sum, a cheap approximation for samples close together
+///
+/// Each sample is flipped into the hemisphere of the first before summing.
+/// Returns `None` if there are no samples or the sum vanishes. Panics if
+/// `weights` and `samples` differ in length.
+pub fn approximate_average<T>(samples: &[UnitQuaternion<T>], weights: &[T])
+    -> Option<UnitQuaternion<T>>
+    where T: Float
+{
+    assert_eq!(samples.len(), weights.len(), "one weight is needed per sample");
+
+    let first = match samples.first() {
+        Some(q) => **q,
+        None => return None,
+    };
+    let zero = T::zero();
+    let mut sum = Quaternion::new(zero, zero, zero, zero);
+    for (q, &w) in samples.iter().zip(weights.iter()) {
+        let q = if dot(**q, first) < zero { -**q } else { **q };
+        sum += q * w;
+    }
+    try_normalize(sum, zero).map(UnitQuaternion::new_unchecked)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dot;
+    use test_util::{assert_same_rotation, rot};
+
+    #[test]
+    fn test_average_sign_invariant() {
+        let samples = [rot([0.0, 0.0, 1.0], 0.2), -rot([0.0, 0.0, 1.0], 0.4),
+                       rot([0.0, 0.0, 1.0], 0.6)];
+        let q = average(&samples).unwrap();
+        assert_same_rotation(q, rot([0.0, 0.0, 1.0], 0.4), 1e-12);
+        assert!(dot(*q, *samples[0]) > 0.0);
+    }
+
+    #[test]
+    fn test_weighted_average() {
+        let samples = [rot([1.0, 0.0, 0.0], 0.5), rot([0.0, 1.0, 0.0], 0.5)];
+        let q = weighted_average(&samples, &[1.0, 0.0]).unwrap();
+        assert_same_rotation(q, samples[0], 1e-12);
+
+        // equal weights land on the midpoint
+        let q = weighted_average(&samples, &[2.0, 2.0]).unwrap();
+        let mid = samples[0].slerp(samples[1], 0.5);
+        assert_same_rotation(q, mid, 1e-12);
+
+        assert_eq!(weighted_average::<f64>(&[], &[]), None);
+        assert_eq!(weighted_average(&samples, &[0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn test_approximate_average() {
+        let samples = [rot([0.3, 1.0, 0.0], 0.10), -rot([0.3, 1.0, 0.0], 0.12),
+                       rot([0.3, 1.0, 0.0], 0.14)];
+        let exact = weighted_average(&samples, &[1.0, 2.0, 1.0]).unwrap();
+        let approx = approximate_average(&samples, &[1.0, 2.0, 1.0]).unwrap();
+        assert_same_rotation(exact, approx, 1e-12);
+        assert!(dot(*approx, *samples[0]) > 0.0);
+    }
+}
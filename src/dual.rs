@@ -0,0 +1,462 @@
+//! Dual quaternions representing rigid body transforms
+//!
+//! A unit dual quaternion `real + eps * dual` with rotation `r` and
+//! translation `t` has `real = r` and `dual = 0.5 * (0, t) * r`, so it rotates
+//! first and then translates. Transforms compose with `*` exactly as rotations
+//! compose with `mul`: `(a * b).transform_point(p)` is
+//! `a.transform_point(b.transform_point(p))`.
+
+use vecmath::traits::Float;
+use vecmath::{vec3_add, vec3_cross, vec3_len, vec3_scale, vec3_sub};
+use std::ops::{Add, Sub, Mul, MulAssign, Neg};
+
+use {Quaternion, UnitQuaternion, conj, dot, id, inverse, len, mul, rotate_vector};
+
+
+/// Dual quaternion `real + eps * dual`, with `eps^2 = 0`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DualQuaternion<T> {
+    /// Real part, the rotation of a unit dual quaternion
+    pub real: Quaternion<T>,
+    /// Dual part, which encodes the translation
+    pub dual: Quaternion<T>,
+}
+
+/// Screw motion: a rotation of `angle` about a line combined with a
+/// translation of `displacement` along it
+///
+/// The line is given in Plücker coordinates: its unit direction `axis` and
+/// its `moment`, `p x axis` for any point `p` on the line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Screw<T> {
+    /// Unit direction of the screw axis
+    pub axis: [T; 3],
+    /// Moment of the screw axis about the origin
+    pub moment: [T; 3],
+    /// Rotation angle about the axis, in radians
+    pub angle: T,
+    /// Translation along the axis
+    pub displacement: T,
+}
+
+impl<T> DualQuaternion<T>
+    where T: Float
+{
+    /// Constructs a dual quaternion from its real and dual parts
+    #[inline(always)]
+    pub fn new(real: Quaternion<T>, dual: Quaternion<T>) -> DualQuaternion<T> {
+        DualQuaternion { real, dual }
+    }
+
+    /// The identity transform
+    #[inline(always)]
+    pub fn identity() -> DualQuaternion<T> {
+        let zero = T::zero();
+        DualQuaternion::new(id(), Quaternion::new(zero, zero, zero, zero))
+    }
+
+    /// Rotation by `rotation` followed by translation by `translation`
+    #[inline(always)]
+    pub fn from_rotation_translation(rotation: UnitQuaternion<T>, translation: [T; 3])
+        -> DualQuaternion<T>
+    {
+        let half = T::from_f64(0.5);
+        let t: Quaternion<T> = (T::zero(), vec3_scale(translation, half)).into();
+        DualQuaternion::new(*rotation, mul(t, *rotation))
+    }
+
+    /// Pure rotation
+    #[inline(always)]
+    pub fn from_rotation(rotation: UnitQuaternion<T>) -> DualQuaternion<T> {
+        DualQuaternion::from_rotation_translation(rotation, [T::zero(); 3])
+    }
+
+    /// Pure translation
+    #[inline(always)]
+    pub fn from_translation(translation: [T; 3]) -> DualQuaternion<T> {
+        DualQuaternion::from_rotation_translation(UnitQuaternion::identity(), translation)
+    }
+
+    /// Rotation part, assuming the dual quaternion is normalized
+    #[inline(always)]
+    pub fn rotation(self) -> UnitQuaternion<T> {
+        UnitQuaternion::new_unchecked(self.real)
+    }
+
+    /// Translation part, assuming the dual quaternion is normalized
+    #[inline(always)]
+    pub fn translation(self) -> [T; 3] {
+        let two = T::one() + T::one();
+        vec3_scale(mul(self.dual, conj(self.real)).vector(), two)
+    }
+
+    /// Quaternion conjugate of both parts, the inverse of a unit dual quaternion
+    #[inline(always)]
+    pub fn conj(self) -> DualQuaternion<T> {
+        DualQuaternion::new(conj(self.real), conj(self.dual))
+    }
+
+    /// Multiplicative inverse
+    ///
+    /// Returns `None` when the real part is zero.
+    #[inline(always)]
+    pub fn inverse(self) -> Option<DualQuaternion<T>> {
+        let r = inverse(self.real)?;
+        Some(DualQuaternion::new(r, -mul(mul(r, self.dual), r)))
+    }
+
+    /// Scales to unit length and makes the dual part orthogonal to the real part
+    ///
+    /// Returns `None` when the real part is zero.
+    #[inline(always)]
+    pub fn normalize(self) -> Option<DualQuaternion<T>> {
+        let l = len(self.real);
+        if l <= T::zero() {
+            return None;
+        }
+        let real = self.real / l;
+        let dual = self.dual / l;
+        Some(DualQuaternion::new(real, dual - real * dot(real, dual)))
+    }
+
+    /// Transforms a point, applying both rotation and translation
+    #[inline(always)]
+    pub fn transform_point(self, p: [T; 3]) -> [T; 3] {
+        vec3_add(self.transform_vector(p), self.translation())
+    }
+
+    /// Transforms a direction, applying only the rotation
+    #[inline(always)]
+    pub fn transform_vector(self, v: [T; 3]) -> [T; 3] {
+        rotate_vector(self.rotation(), v)
+    }
+
+    /// Screw parameters of a normalized dual quaternion
+    ///
+    /// The angle is in `[0, pi]`. Pure translations, and rotations too small to
+    /// locate their axis, report an angle of zero and the translation
+    /// direction as axis through the origin, and the identity reports the x
+    /// axis.
+    pub fn to_screw(self) -> Screw<T> {
+        let zero = T::zero();
+        let two = T::one() + T::one();
+
+        // q and -q are the same transform; pick the one turning at most pi
+        let dq = if self.real.w < zero { -self } else { self };
+        let v = dq.real.vector();
+        let s = vec3_len(v);
+
+        if s <= T::from_f64(1e-6) {
+            let t = dq.translation();
+            let d = vec3_len(t);
+            let axis = if d > zero {
+                vec3_scale(t, T::one() / d)
+            } else {
+                [T::one(), zero, zero]
+            };
+            return Screw { axis, moment: [zero; 3], angle: zero, displacement: d };
+        }
+
+        let axis = vec3_scale(v, T::one() / s);
+        let displacement = -two * dq.dual.w / s;
+        let along = vec3_scale(axis, displacement / two * dq.real.w);
+        let moment = vec3_scale(vec3_sub(dq.dual.vector(), along), T::one() / s);
+        Screw { axis, moment, angle: two * s.atan2(dq.real.w), displacement }
+    }
+
+    /// Unit dual quaternion performing the given screw motion
+    pub fn from_screw(screw: Screw<T>) -> DualQuaternion<T> {
+        let half = T::from_f64(0.5);
+        let half_angle = screw.angle * half;
+        let (s, c) = (half_angle.sin(), half_angle.cos());
+        let d = screw.displacement * half;
+        let real = (c, vec3_scale(screw.axis, s)).into();
+        let dual = (-d * s, vec3_add(vec3_scale(screw.moment, s),
+                                     vec3_scale(screw.axis, d * c))).into();
+        DualQuaternion::new(real, dual)
+    }
+
+    /// Scales the screw motion of a normalized dual quaternion by `t`, turning
+    /// through `t` times the angle and sliding `t` times the displacement
+    ///
+    /// The shorter of the two screw motions `q` and `-q` describe is used.
+    pub fn pow(self, t: T) -> DualQuaternion<T> {
+        let dq = if self.real.w < T::zero() { -self } else { self };
+        if vec3_len(dq.real.vector()) <= T::from_f64(1e-6) {
+            // the screw axis is too far away to locate, so treat the motion
+            // as a rotation and an independent translation
+            let rotation = dq.rotation().pow(t);
+            return DualQuaternion::from_rotation_translation(
+                rotation, vec3_scale(dq.translation(), t));
+        }
+        let mut screw = dq.to_screw();
+        screw.angle *= t;
+        screw.displacement *= t;
+        DualQuaternion::from_screw(screw)
+    }
+
+    /// Screw linear interpolation (ScLERP) towards `other`
+    ///
+    /// Moves along the constant screw motion from `self` at `t = 0` to `other`
+    /// at `t = 1`, the rigid transform analogue of `slerp`. Both must be
+    /// normalized.
+    pub fn sclerp(self, other: DualQuaternion<T>, t: T) -> DualQuaternion<T> {
+        let other = if dot(self.real, other.real) < T::zero() { -other } else { other };
+        self * (self.conj() * other).pow(t)
+    }
+}
+
+/// Dual quaternion linear blending (DLB) of normalized transforms
+///
+/// The weighted sum, with every transform flipped into the hemisphere of the
+/// one with the largest weight, is normalized. This approximates the screw
+/// motion blend but is cheap and works for any number of transforms. See
+/// Kavan et al., "Geometric Skinning with Approximate Dual Quaternion
+/// Blending" (2008). Returns `None` if there are no transforms or the sum of
+/// the real parts vanishes. Panics if `weights` and `transforms` differ in
+/// length.
+pub fn dlb<T>(transforms: &[DualQuaternion<T>], weights: &[T]) -> Option<DualQuaternion<T>>
+    where T: Float
+{
+    assert_eq!(transforms.len(), weights.len(), "one weight is needed per transform");
+    blend(transforms.iter().cloned().zip(weights.iter().cloned()))
+}
+
+/// `dlb` over `(transform, weight)` pairs, which are iterated twice: once to
+/// find the largest weight and once to sum
+pub(crate) fn blend<T, I>(transforms: I) -> Option<DualQuaternion<T>>
+    where T: Float, I: Iterator<Item = (DualQuaternion<T>, T)> + Clone
+{
+    let mut pivot = None;
+    for (dq, w) in transforms.clone() {
+        match pivot {
+            Some((_, max)) if w <= max => {}
+            _ => pivot = Some((dq.real, w)),
+        }
+    }
+    let (pivot, _) = pivot?;
+
+    let zero = T::zero();
+    let mut sum = DualQuaternion::new(Quaternion::new(zero, zero, zero, zero),
+                                      Quaternion::new(zero, zero, zero, zero));
+    for (dq, w) in transforms {
+        let dq = if dot(dq.real, pivot) < zero { -dq } else { dq };
+        sum = sum + dq * w;
+    }
+    sum.normalize()
+}
+
+impl<T> Screw<T>
+    where T: Float
+{
+    /// Screw about the line through `point` with direction `axis`
+    ///
+    /// The axis is normalized.
+    pub fn through_point(axis: [T; 3], point: [T; 3], angle: T, displacement: T) -> Screw<T> {
+        let axis = vec3_scale(axis, T::one() / vec3_len(axis));
+        Screw { axis, moment: vec3_cross(point, axis), angle, displacement }
+    }
+
+    /// Point on the screw axis closest to the origin
+    pub fn point(&self) -> [T; 3] {
+        vec3_cross(self.axis, self.moment)
+    }
+}
+
+impl<T> Add for DualQuaternion<T>
+    where T: Float
+{
+    type Output = DualQuaternion<T>;
+
+    #[inline(always)]
+    fn add(self, other: DualQuaternion<T>) -> DualQuaternion<T> {
+        DualQuaternion::new(self.real + other.real, self.dual + other.dual)
+    }
+}
+
+impl<T> Sub for DualQuaternion<T>
+    where T: Float
+{
+    type Output = DualQuaternion<T>;
+
+    #[inline(always)]
+    fn sub(self, other: DualQuaternion<T>) -> DualQuaternion<T> {
+        DualQuaternion::new(self.real - other.real, self.dual - other.dual)
+    }
+}
+
+/// Composition, applying `other` first and then `self`
+impl<T> Mul for DualQuaternion<T>
+    where T: Float
+{
+    type Output = DualQuaternion<T>;
+
+    #[inline(always)]
+    fn mul(self, other: DualQuaternion<T>) -> DualQuaternion<T> {
+        DualQuaternion::new(mul(self.real, other.real),
+                            ::add(mul(self.real, other.dual), mul(self.dual, other.real)))
+    }
+}
+
+impl<T> Mul<T> for DualQuaternion<T>
+    where T: Float
+{
+    type Output = DualQuaternion<T>;
+
+    #[inline(always)]
+    fn mul(self, t: T) -> DualQuaternion<T> {
+        DualQuaternion::new(self.real * t, self.dual * t)
+    }
+}
+
+impl<T> MulAssign for DualQuaternion<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn mul_assign(&mut self, other: DualQuaternion<T>) {
+        *self = *self * other;
+    }
+}
+
+impl<T> Neg for DualQuaternion<T>
+    where T: Float
+{
+    type Output = DualQuaternion<T>;
+
+    #[inline(always)]
+    fn neg(self) -> DualQuaternion<T> {
+        DualQuaternion::new(-self.real, -self.dual)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{assert_close, rot};
+
+    #[test]
+    fn test_rotation_translation() {
+        let q = rot([0.2, 1.0, -0.5], 1.3);
+        let t = [1.0, -2.0, 0.5];
+        let dq = DualQuaternion::from_rotation_translation(q, t);
+        assert_eq!(*dq.rotation(), *q);
+        assert_close(dq.translation(), t, 1e-10);
+
+        let p = [0.3, 0.4, 5.0];
+        assert_close(dq.transform_point(p), vec3_add(rotate_vector(q, p), t), 1e-10);
+        assert_close(dq.transform_vector(p), rotate_vector(q, p), 1e-10);
+    }
+
+    #[test]
+    fn test_compose_and_inverse() {
+        let a = DualQuaternion::from_rotation_translation(rot([1.0, 0.0, 0.0], 0.5), [1.0, 2.0, 3.0]);
+        let b = DualQuaternion::from_rotation_translation(rot([0.0, 1.0, 1.0], -2.0), [-1.0, 0.0, 4.0]);
+        let p = [2.0, -1.0, 0.5];
+        assert_close((a * b).transform_point(p), a.transform_point(b.transform_point(p)), 1e-10);
+
+        let inv = a.inverse().unwrap();
+        assert_close((inv * a).transform_point(p), p, 1e-10);
+        assert_close(a.conj().transform_point(a.transform_point(p)), p, 1e-10);
+        let zero = DualQuaternion::new(Quaternion::new(0.0, 0.0, 0.0, 0.0), id());
+        assert_eq!(zero.inverse(), None);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let a = DualQuaternion::from_rotation_translation(rot([1.0, 1.0, 0.0], 0.8), [0.5, 0.0, 1.0]);
+        let mut drifted = a * 3.0;
+        drifted.dual += a.real * 0.25;
+        let n = drifted.normalize().unwrap();
+        assert!((::square_len(n.real) - 1.0).abs() < 1e-12);
+        assert!(dot(n.real, n.dual).abs() < 1e-12);
+        assert_close(n.translation(), a.translation(), 1e-10);
+    }
+
+    #[test]
+    fn test_screw() {
+        // a quarter turn about the z axis through (1, 0, 0), rising by 2
+        let screw = Screw::through_point([0.0, 0.0, 1.0], [1.0, 0.0, 0.0],
+                                         ::std::f64::consts::FRAC_PI_2, 2.0);
+        let dq = DualQuaternion::from_screw(screw);
+        assert_close(dq.transform_point([2.0, 0.0, 0.0]), [1.0, 1.0, 2.0], 1e-10);
+        assert_close(dq.transform_point([1.0, 0.0, 0.0]), [1.0, 0.0, 2.0], 1e-10);
+
+        let s = dq.to_screw();
+        assert_close(s.axis, screw.axis, 1e-10);
+        assert_close(s.moment, screw.moment, 1e-10);
+        assert_close(s.point(), [1.0, 0.0, 0.0], 1e-10);
+        assert!((s.angle - screw.angle).abs() < 1e-10);
+        assert!((s.displacement - 2.0).abs() < 1e-10);
+
+        // the sign of the dual quaternion does not matter
+        assert_eq!((-dq).to_screw(), s);
+    }
+
+    #[test]
+    fn test_sclerp() {
+        let a = DualQuaternion::from_rotation_translation(rot([0.0, 0.0, 1.0], 0.3), [1.0, 0.0, 0.0]);
+        let screw = Screw::through_point([0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 1.2, 0.8);
+        let b = DualQuaternion::from_screw(screw) * a;
+
+        let p = [0.5, -0.5, 2.0];
+        assert_close(a.sclerp(b, 0.0).transform_point(p), a.transform_point(p), 1e-10);
+        assert_close(a.sclerp(b, 1.0).transform_point(p), b.transform_point(p), 1e-10);
+        assert_close(a.sclerp(-b, 1.0).transform_point(p), b.transform_point(p), 1e-10);
+
+        // halfway along the screw is half the rotation and displacement
+        let half = Screw { angle: 0.6, displacement: 0.4, ..screw };
+        let mid = DualQuaternion::from_screw(half) * a;
+        assert_close(a.sclerp(b, 0.5).transform_point(p), mid.transform_point(p), 1e-10);
+
+        // pure translations interpolate linearly
+        let t = DualQuaternion::from_translation([2.0, 4.0, -6.0]);
+        let i = DualQuaternion::identity();
+        assert_close(i.sclerp(t, 0.25).translation(), [0.5, 1.0, -1.5], 1e-10);
+    }
+
+    #[test]
+    fn test_dlb() {
+        let a = DualQuaternion::from_rotation_translation(rot([1.0, 0.0, 0.0], 0.4), [1.0, 2.0, 0.0]);
+        let b = DualQuaternion::from_rotation_translation(rot([1.0, 0.0, 0.0], 1.0), [1.0, 2.0, 1.0]);
+
+        let blended = dlb(&[a, -b], &[1.0, 0.0]).unwrap();
+        assert_close(blended.translation(), a.translation(), 1e-10);
+
+        // blending rotations about a common axis interpolates the angle
+        let mid = dlb(&[a, -b], &[0.5, 0.5]).unwrap();
+        assert!((mid.rotation().angle() - 0.7).abs() < 1e-10);
+        assert!(dot(mid.real, mid.dual).abs() < 1e-12);
+
+        assert_eq!(dlb::<f64>(&[], &[]), None);
+    }
+
+    #[test]
+    fn test_dlb_pivot() {
+        // the hemisphere is chosen by the heaviest transform, not the first
+        let joints = [
+            DualQuaternion::identity(),
+            DualQuaternion::from_rotation(rot([1.0, 0.0, 0.0], 3.0)),
+            DualQuaternion::from_rotation(rot([1.0, 0.0, 0.0], 3.3)),
+        ];
+        let mid = dlb(&joints, &[0.0, 0.5, 0.5]).unwrap();
+        let expected = [0.0, 3.15f64.cos(), 3.15f64.sin()];
+        assert_close(mid.transform_vector([0.0, 1.0, 0.0]), expected, 1e-10);
+        assert_eq!(dlb(&joints, &[0.0, 0.5, 0.5]), dlb(&joints[1..], &[0.5, 0.5]));
+    }
+
+    #[test]
+    fn test_screw_translation() {
+        let dq = DualQuaternion::from_translation([0.0, 3.0, 4.0]);
+        let s = dq.to_screw();
+        assert_eq!(s.angle, 0.0);
+        assert_close(s.axis, [0.0, 0.6, 0.8], 1e-10);
+        assert!((s.displacement - 5.0).abs() < 1e-12);
+        assert_close(DualQuaternion::from_screw(s).translation(), [0.0, 3.0, 4.0], 1e-10);
+
+        let s = DualQuaternion::<f64>::identity().to_screw();
+        assert_eq!(s.axis, [1.0, 0.0, 0.0]);
+        assert_eq!(s.displacement, 0.0);
+    }
+}
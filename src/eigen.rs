@@ -0,0 +1,156 @@
+//! Eigen-decomposition of symmetric 4x4 matrices
+//!
+//! Quaternion fitting problems (averaging, Davenport's q-method, Horn's
+//! method) all reduce to finding the dominant eigenvector of a symmetric 4x4
+//! matrix, with components ordered `[w, x, y, z]`.
+
+use vecmath::traits::Float;
+use vecmath::{Matrix3, Matrix4};
+
+use Quaternion;
+
+
+/// Eigenvalues and eigenvectors of a symmetric matrix by cyclic Jacobi rotations
+///
+/// Column `i` of the returned matrix, `v[k][i]`, is the unit eigenvector of
+/// eigenvalue `i`. Only the upper triangle is assumed to be meaningful.
+#[allow(clippy::needless_range_loop)]
+pub fn symmetric_eigen<T>(m: Matrix4<T>) -> ([T; 4], Matrix4<T>)
+    where T: Float
+{
+    let zero = T::zero();
+    let one = T::one();
+    let two = one + one;
+
+    let mut a = m;
+    for p in 0..4 {
+        for q in 0..p {
+            a[p][q] = a[q][p];
+        }
+    }
+    let mut v = [[zero; 4]; 4];
+    for (i, row) in v.iter_mut().enumerate() {
+        row[i] = one;
+    }
+
+    let mut norm = zero;
+    for row in a.iter() {
+        for &x in row.iter() {
+            norm += x * x;
+        }
+    }
+    let tolerance = norm * T::from_f64(1e-24);
+
+    for _ in 0..50 {
+        let mut off = zero;
+        for p in 0..4 {
+            for q in (p + 1)..4 {
+                off += a[p][q] * a[p][q];
+            }
+        }
+        if off <= tolerance {
+            break;
+        }
+
+        for p in 0..4 {
+            for q in (p + 1)..4 {
+                let apq = a[p][q];
+                if apq == zero {
+                    continue;
+                }
+
+                // rotation zeroing a[p][q], taking the smaller root for stability
+                let theta = (a[q][q] - a[p][p]) / (two * apq);
+                let abs_theta = if theta < zero { -theta } else { theta };
+                let mut t = one / (abs_theta + (theta * theta + one).sqrt());
+                if theta < zero {
+                    t = -t;
+                }
+                let c = one / (t * t + one).sqrt();
+                let s = t * c;
+
+                for row in a.iter_mut() {
+                    let (akp, akq) = (row[p], row[q]);
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                for k in 0..4 {
+                    let (apk, aqk) = (a[p][k], a[q][k]);
+                    a[p][k] = c * apk - s * aqk;
+                    a[q][k] = s * apk + c * aqk;
+                }
+                for row in v.iter_mut() {
+                    let (vkp, vkq) = (row[p], row[q]);
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    ([a[0][0], a[1][1], a[2][2], a[3][3]], v)
+}
+
+/// Davenport's symmetric `K` matrix, ordered `[w, x, y, z]`, for which
+/// `q^T K q` is `trace(R(q)^T B)` for profile matrix `B`
+#[allow(clippy::needless_range_loop)]
+pub fn davenport_k<T>(b: Matrix3<T>) -> Matrix4<T>
+    where T: Float
+{
+    let sigma = b[0][0] + b[1][1] + b[2][2];
+    let z = [b[2][1] - b[1][2], b[0][2] - b[2][0], b[1][0] - b[0][1]];
+
+    let mut k = [[T::zero(); 4]; 4];
+    k[0][0] = sigma;
+    for i in 0..3 {
+        k[0][i + 1] = z[i];
+        k[i + 1][0] = z[i];
+        for j in 0..3 {
+            k[i + 1][j + 1] = b[i][j] + b[j][i];
+        }
+        k[i + 1][i + 1] -= sigma;
+    }
+    k
+}
+
+/// Largest eigenvalue of a symmetric matrix and its eigenvector as a quaternion
+pub fn dominant_eigenvector<T>(m: Matrix4<T>) -> (T, Quaternion<T>)
+    where T: Float
+{
+    let (values, vectors) = symmetric_eigen(m);
+    let mut best = 0;
+    for i in 1..4 {
+        if values[i] > values[best] {
+            best = i;
+        }
+    }
+    let q = Quaternion::new(vectors[0][best], vectors[1][best],
+                            vectors[2][best], vectors[3][best]);
+    (values[best], q)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symmetric_eigen() {
+        let m: Matrix4<f64> = [
+            [4.0, 1.0, -2.0, 2.0],
+            [1.0, 2.0, 0.0, 1.0],
+            [-2.0, 0.0, 3.0, -2.0],
+            [2.0, 1.0, -2.0, -1.0],
+        ];
+        let (values, vectors) = symmetric_eigen(m);
+        for i in 0..4 {
+            for r in 0..4 {
+                let mv: f64 = (0..4).map(|c| m[r][c] * vectors[c][i]).sum();
+                assert!((mv - values[i] * vectors[r][i]).abs() < 1e-9);
+            }
+        }
+
+        let (value, _) = dominant_eigenvector(m);
+        assert!(values.iter().all(|&v| v <= value));
+    }
+}
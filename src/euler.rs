@@ -0,0 +1,260 @@
+//! Euler angle conversions for all twelve rotation sequences
+//!
+//! A sequence names the axes the three angles rotate about, in order. With
+//! intrinsic angles each rotation is about an axis of the already rotated
+//! frame; with extrinsic angles every rotation is about the fixed frame. The
+//! aerospace 3-2-1 yaw, pitch, roll convention is intrinsic `ZYX` with angles
+//! `[yaw, pitch, roll]`.
+//!
+//! Converting from a quaternion follows Bernardes and Viollet, "Quaternion to
+//! Euler angles conversion: A direct, general and computationally efficient
+//! method" (2022).
+
+use vecmath::traits::Float;
+use std::f64::consts::PI;
+
+use {Quaternion, UnitQuaternion, id, mul};
+
+
+/// Order of the axes rotated about
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sequence {
+    XYZ, XZY, YXZ, YZX, ZXY, ZYX,
+    XYX, XZX, YXY, YZY, ZXZ, ZYZ,
+}
+
+impl Sequence {
+    /// All twelve sequences, Tait–Bryan first then proper Euler
+    pub const ALL: [Sequence; 12] = [
+        Sequence::XYZ, Sequence::XZY, Sequence::YXZ, Sequence::YZX, Sequence::ZXY, Sequence::ZYX,
+        Sequence::XYX, Sequence::XZX, Sequence::YXY, Sequence::YZY, Sequence::ZXZ, Sequence::ZYZ,
+    ];
+
+    /// Axis indices, 0 for x through 2 for z
+    pub fn axes(self) -> [usize; 3] {
+        match self {
+            Sequence::XYZ => [0, 1, 2],
+            Sequence::XZY => [0, 2, 1],
+            Sequence::YXZ => [1, 0, 2],
+            Sequence::YZX => [1, 2, 0],
+            Sequence::ZXY => [2, 0, 1],
+            Sequence::ZYX => [2, 1, 0],
+            Sequence::XYX => [0, 1, 0],
+            Sequence::XZX => [0, 2, 0],
+            Sequence::YXY => [1, 0, 1],
+            Sequence::YZY => [1, 2, 1],
+            Sequence::ZXZ => [2, 0, 2],
+            Sequence::ZYZ => [2, 1, 2],
+        }
+    }
+
+    /// Whether the first and last axes coincide, as in `ZXZ`
+    pub fn is_proper_euler(self) -> bool {
+        let a = self.axes();
+        a[0] == a[2]
+    }
+}
+
+/// Whether the axes move with the body or stay fixed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// Each rotation is about an axis of the frame rotated so far
+    Intrinsic,
+    /// Each rotation is about an axis of the fixed frame
+    Extrinsic,
+}
+
+/// Euler angles recovered from a quaternion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EulerAngles<T> {
+    /// Angles in radians, in sequence order
+    ///
+    /// The middle angle is in `[-pi/2, pi/2]` for Tait–Bryan sequences and
+    /// `[0, pi]` for proper Euler sequences; the others are in `(-pi, pi]`.
+    pub angles: [T; 3],
+    /// Whether the middle angle was at a singularity
+    ///
+    /// Only the sum or difference of the outer angles is then determined, so
+    /// the third angle is set to zero and the first carries the rotation.
+    pub gimbal_lock: bool,
+}
+
+/// Rotation of `theta` about coordinate axis `axis`
+#[inline(always)]
+fn axis_rotation<T>(axis: usize, theta: T) -> Quaternion<T>
+    where T: Float
+{
+    let half = theta * T::from_f64(0.5);
+    let mut q = Quaternion::new(half.cos(), T::zero(), T::zero(), T::zero());
+    q[axis + 1] = half.sin();
+    q
+}
+
+/// Wraps an angle in `(-2pi, 2pi]` into `(-pi, pi]`
+#[inline(always)]
+fn wrap<T>(a: T) -> T
+    where T: Float
+{
+    let pi = T::from_f64(PI);
+    let two_pi = pi + pi;
+    if a > pi {
+        a - two_pi
+    } else if a <= -pi {
+        a + two_pi
+    } else {
+        a
+    }
+}
+
+/// Converts Euler angles to a unit quaternion
+pub fn to_quaternion<T>(angles: [T; 3], seq: Sequence, frame: Frame) -> UnitQuaternion<T>
+    where T: Float
+{
+    let axes = seq.axes();
+    let mut q = id();
+    for n in 0..3 {
+        let r = axis_rotation(axes[n], angles[n]);
+        q = match frame {
+            Frame::Intrinsic => mul(q, r),
+            Frame::Extrinsic => mul(r, q),
+        };
+    }
+    UnitQuaternion::new_unchecked(q)
+}
+
+/// Converts a unit quaternion to Euler angles
+pub fn from_quaternion<T>(q: UnitQuaternion<T>, seq: Sequence, frame: Frame) -> EulerAngles<T>
+    where T: Float
+{
+    let [i, j, k] = seq.axes();
+    match frame {
+        Frame::Extrinsic => extrinsic(*q, i, j, k, true),
+        Frame::Intrinsic => {
+            // intrinsic i-j-k by (a, b, c) is extrinsic k-j-i by (c, b, a)
+            let e = extrinsic(*q, k, j, i, false);
+            EulerAngles {
+                angles: [e.angles[2], e.angles[1], e.angles[0]],
+                gimbal_lock: e.gimbal_lock,
+            }
+        }
+    }
+}
+
+/// Extrinsic angles about axes `i`, `j`, `k`
+///
+/// At gimbal lock the last angle is zeroed if `zero_last`, else the first.
+fn extrinsic<T>(q: Quaternion<T>, i: usize, j: usize, k: usize,
+                zero_last: bool) -> EulerAngles<T>
+    where T: Float
+{
+    let zero = T::zero();
+    let two = T::one() + T::one();
+    let pi = T::from_f64(PI);
+    let tolerance = T::from_f64(1e-6);
+
+    let proper = i == k;
+    let k = if proper { 3 - i - j } else { k };
+    // parity of the permutation (i, j, k)
+    let (si, sj, sk) = (i as i32, j as i32, k as i32);
+    let parity = T::from_i32((si - sj) * (sj - sk) * (sk - si) / 2);
+
+    let (qi, qj, qk) = (q[i + 1], q[j + 1], q[k + 1] * parity);
+    let (a, b, c, d) = if proper {
+        (q.w, qi, qj, qk)
+    } else {
+        (q.w - qj, qi + qk, qj + q.w, qk - qi)
+    };
+
+    let mut theta2 = two * (c * c + d * d).sqrt().atan2((a * a + b * b).sqrt());
+    let plus = b.atan2(a);
+    let minus = d.atan2(c);
+
+    let lock_at_zero = theta2 < tolerance;
+    let lock_at_pi = theta2 > pi - tolerance;
+    let (theta1, mut theta3) = if lock_at_zero {
+        // only theta1 + theta3 is determined
+        if zero_last { (two * plus, zero) } else { (zero, two * plus) }
+    } else if lock_at_pi {
+        // only theta3 - theta1 is determined
+        if zero_last { (-two * minus, zero) } else { (zero, two * minus) }
+    } else {
+        (plus - minus, plus + minus)
+    };
+
+    if !proper {
+        theta3 *= parity;
+        theta2 -= pi / two;
+    }
+
+    EulerAngles {
+        angles: [wrap(theta1), theta2, wrap(theta3)],
+        gimbal_lock: lock_at_zero || lock_at_pi,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rotate_vector;
+    use test_util::assert_same_rotation;
+
+    static EPSILON: f64 = 0.000001;
+
+    #[test]
+    fn test_round_trip() {
+        let samples: [[f64; 3]; 4] = [[0.3, 0.4, -1.2], [-2.5, 1.1, 0.7], [3.0, -0.2, -3.0], [0.0, 0.9, 0.0]];
+        for &seq in Sequence::ALL.iter() {
+            for &frame in [Frame::Intrinsic, Frame::Extrinsic].iter() {
+                for s in samples.iter() {
+                    let mut angles = *s;
+                    if seq.is_proper_euler() {
+                        angles[1] = angles[1].abs() + 0.5;
+                    }
+                    let q = to_quaternion(angles, seq, frame);
+                    let e = from_quaternion(q, seq, frame);
+                    assert!(!e.gimbal_lock);
+                    for n in 0..3 {
+                        assert!((e.angles[n] - angles[n]).abs() < EPSILON,
+                                "{:?} {:?}: {:?} != {:?}", seq, frame, e.angles, angles);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gimbal_lock() {
+        use std::f64::consts::FRAC_PI_2;
+
+        for &seq in Sequence::ALL.iter() {
+            for &frame in [Frame::Intrinsic, Frame::Extrinsic].iter() {
+                let middles = if seq.is_proper_euler() {
+                    [0.0, PI]
+                } else {
+                    [FRAC_PI_2, -FRAC_PI_2]
+                };
+                for &middle in middles.iter() {
+                    let q = to_quaternion([0.4, middle, 0.7], seq, frame);
+                    let e = from_quaternion(q, seq, frame);
+                    assert!(e.gimbal_lock, "{:?} {:?} {}", seq, frame, middle);
+                    assert_eq!(e.angles[2], 0.0);
+                    assert_same_rotation(to_quaternion(e.angles, seq, frame), q, 1e-12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_yaw_pitch_roll() {
+        let yaw = to_quaternion([PI / 2.0, 0.0, 0.0], Sequence::ZYX, Frame::Intrinsic);
+        let v = rotate_vector(yaw, [1.0, 0.0, 0.0]);
+        assert!(v[0].abs() < EPSILON && (v[1] - 1.0).abs() < EPSILON);
+
+        // intrinsic ZYX is extrinsic XYZ with the angles reversed
+        let a = to_quaternion([0.1, 0.2, 0.3], Sequence::ZYX, Frame::Intrinsic);
+        let b = to_quaternion([0.3, 0.2, 0.1], Sequence::XYZ, Frame::Extrinsic);
+        assert_same_rotation(a, b, 1e-12);
+    }
+}
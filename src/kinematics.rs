@@ -0,0 +1,208 @@
+//! Rotational kinematics of a unit quaternion orientation
+//!
+//! The orientation `q` maps body coordinates to world coordinates, so
+//! `rotate_vector(q, v_body)` is `v_body` seen from the world frame. Angular
+//! velocity can be expressed in either frame: a gyroscope measures it in the
+//! body frame, and `q_dot = 0.5 * q * (0, omega)`, while in the world frame
+//! `q_dot = 0.5 * (0, omega) * q`.
+
+use vecmath::traits::Float;
+use vecmath::vec3_scale;
+
+use {Quaternion, UnitQuaternion, conj, mul, normalize, from_rotation_vector,
+     to_rotation_vector};
+
+
+/// Frame an angular velocity is expressed in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frame {
+    /// Axes fixed to the rotating body, as measured by a gyroscope
+    Body,
+    /// Axes fixed to the world
+    World,
+}
+
+/// Time derivative of `q` under angular velocity `omega`
+#[inline(always)]
+pub fn q_dot<T>(q: Quaternion<T>, omega: [T; 3], frame: Frame) -> Quaternion<T>
+    where T: Float
+{
+    let w: Quaternion<T> = (T::zero(), vec3_scale(omega, T::from_f64(0.5))).into();
+    match frame {
+        Frame::Body => mul(q, w),
+        Frame::World => mul(w, q),
+    }
+}
+
+/// Integrates a constant angular velocity over `dt` with the exponential map
+///
+/// This is exact when `omega` does not change during the step.
+#[inline(always)]
+pub fn integrate<T>(q: UnitQuaternion<T>, omega: [T; 3], dt: T, frame: Frame)
+    -> UnitQuaternion<T>
+    where T: Float
+{
+    let step = from_rotation_vector(vec3_scale(omega, dt));
+    match frame {
+        Frame::Body => q * step,
+        Frame::World => step * q,
+    }
+}
+
+/// Integrates over `dt` with a single forward Euler step, then renormalizes
+///
+/// Cheaper than `integrate` but only first order accurate.
+#[inline(always)]
+pub fn integrate_euler<T>(q: UnitQuaternion<T>, omega: [T; 3], dt: T, frame: Frame)
+    -> UnitQuaternion<T>
+    where T: Float
+{
+    let q = *q;
+    UnitQuaternion::new_unchecked(normalize(q + q_dot(q, omega, frame) * dt))
+}
+
+/// Integrates a time varying angular velocity over `dt` with classic fourth
+/// order Runge–Kutta, then renormalizes
+///
+/// `omega(t)` is sampled at `t = 0`, `dt / 2` and `dt` from the start of the step.
+pub fn integrate_rk4<T, F>(q: UnitQuaternion<T>, omega: F, dt: T, frame: Frame)
+    -> UnitQuaternion<T>
+    where T: Float, F: Fn(T) -> [T; 3]
+{
+    let two = T::one() + T::one();
+    let half_dt = dt / two;
+    let q = *q;
+
+    let w0 = omega(T::zero());
+    let w_mid = omega(half_dt);
+    let w1 = omega(dt);
+
+    let k1 = q_dot(q, w0, frame);
+    let k2 = q_dot(q + k1 * half_dt, w_mid, frame);
+    let k3 = q_dot(q + k2 * half_dt, w_mid, frame);
+    let k4 = q_dot(q + k3 * dt, w1, frame);
+
+    let sum = k1 + (k2 + k3) * two + k4;
+    UnitQuaternion::new_unchecked(normalize(q + sum * (dt / T::from_f64(6.0))))
+}
+
+/// Angular velocity of the orientation `q` given its time derivative, the
+/// inverse of `q_dot`
+#[inline(always)]
+pub fn angular_velocity_from_q_dot<T>(q: UnitQuaternion<T>, q_dot: Quaternion<T>,
+                                      frame: Frame) -> [T; 3]
+    where T: Float
+{
+    let two = T::one() + T::one();
+    let w = match frame {
+        Frame::Body => mul(conj(*q), q_dot),
+        Frame::World => mul(q_dot, conj(*q)),
+    };
+    vec3_scale(w.vector(), two)
+}
+
+/// Constant angular velocity that takes `q0` to `q1` in time `dt`, the inverse
+/// of `integrate`
+///
+/// The shorter of the two possible rotations between the orientations is
+/// assumed. `dt` must be nonzero.
+#[inline(always)]
+pub fn angular_velocity<T>(q0: UnitQuaternion<T>, q1: UnitQuaternion<T>, dt: T,
+                           frame: Frame) -> [T; 3]
+    where T: Float
+{
+    let delta = match frame {
+        Frame::Body => q0.inverse() * q1,
+        Frame::World => q1 * q0.inverse(),
+    };
+    vec3_scale(to_rotation_vector(delta), T::one() / dt)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rotate_vector;
+    use test_util::{assert_same_rotation, rot};
+
+    static EPSILON: f64 = 0.000001;
+
+    #[test]
+    fn test_integrate_constant() {
+        let q = rot([1.0, 0.0, 0.0], 0.3);
+        let r = integrate(q, [0.0, 0.0, 2.0], 0.5, Frame::Body);
+        assert_same_rotation(r, q * rot([0.0, 0.0, 1.0], 1.0), 1e-12);
+        let r = integrate(q, [0.0, 0.0, 2.0], 0.5, Frame::World);
+        assert_same_rotation(r, rot([0.0, 0.0, 1.0], 1.0) * q, 1e-12);
+    }
+
+    #[test]
+    fn test_body_world_agree() {
+        // a body rate seen from the world frame gives the same motion
+        let q = rot([0.2, 1.0, -0.4], 1.1);
+        let omega_body = [0.5, -1.0, 0.25];
+        let omega_world = rotate_vector(q, omega_body);
+        let a = integrate(q, omega_body, 0.1, Frame::Body);
+        let b = integrate(q, omega_world, 0.1, Frame::World);
+        assert_same_rotation(a, b, 1e-12);
+    }
+
+    #[test]
+    fn test_angular_velocity() {
+        let q0 = rot([0.3, -0.2, 1.0], 0.9);
+        let omega = [0.4, -1.5, 0.7];
+        for &frame in [Frame::Body, Frame::World].iter() {
+            let q1 = integrate(q0, omega, 0.25, frame);
+            let w = angular_velocity(q0, q1, 0.25, frame);
+            for i in 0..3 {
+                assert!((w[i] - omega[i]).abs() < EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn test_q_dot_round_trip() {
+        let q = rot([1.0, 2.0, 3.0], -0.8);
+        let omega = [0.1, 2.0, -0.6];
+        for &frame in [Frame::Body, Frame::World].iter() {
+            let w = angular_velocity_from_q_dot(q, q_dot(*q, omega, frame), frame);
+            for i in 0..3 {
+                assert!((w[i] - omega[i]).abs() < EPSILON);
+            }
+        }
+
+        // matches a finite difference of the exact integration
+        let h = 1e-6;
+        let q1 = integrate(q, omega, h, Frame::Body);
+        let numeric = (*q1 - *q) / h;
+        assert!(::square_len(numeric - q_dot(*q, omega, Frame::Body)) < 1e-10);
+    }
+
+    #[test]
+    fn test_integrate_euler() {
+        let q = rot([0.0, 1.0, 0.0], 0.4);
+        let omega = [0.3, 0.2, -0.1];
+        let mut approx = q;
+        for _ in 0..1000 {
+            approx = integrate_euler(approx, omega, 0.001, Frame::Body);
+        }
+        let exact = integrate(q, omega, 1.0, Frame::Body);
+        assert_same_rotation(approx, exact, 1e-8);
+    }
+
+    #[test]
+    fn test_integrate_rk4() {
+        let q = rot([1.0, 1.0, 0.0], 0.7);
+        let r = integrate_rk4(q, |_| [0.0, 1.0, 0.0], 0.2, Frame::World);
+        // a single step has a fifth order local error
+        assert_same_rotation(r, integrate(q, [0.0, 1.0, 0.0], 0.2, Frame::World), 1e-6);
+
+        // omega = t about a fixed axis turns through t^2 / 2
+        let mut r = q;
+        for i in 0..10 {
+            let t0 = 0.1 * i as f64;
+            r = integrate_rk4(r, |t| [0.0, 0.0, t0 + t], 0.1, Frame::Body);
+        }
+        assert_same_rotation(r, q * rot([0.0, 0.0, 1.0], 0.5), 1e-7);
+    }
+}
@@ -8,12 +8,299 @@
 extern crate vecmath;
 
 use vecmath::traits::Float;
+use vecmath::{Matrix3, Matrix4, mat3_transposed, mat4_transposed};
 use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Sub, SubAssign, Mul, MulAssign, Div, DivAssign, Neg,
+               Index, IndexMut};
 
+pub use dual::DualQuaternion;
+pub use pose::Pose;
+pub use unit::UnitQuaternion;
 
-/// Quaternion type alias
-pub type Quaternion<T> = (T, [T; 3]);
+pub mod ahrs;
+pub mod attitude;
+pub mod average;
+pub mod dual;
+pub mod euler;
+pub mod kinematics;
+pub mod mekf;
+pub mod pose;
+pub mod qcp;
+pub mod registration;
+pub mod skinning;
+pub mod so3;
+pub mod spline;
+pub mod unit;
+pub mod wahba;
 
+mod eigen;
+
+
+/// Quaternion with scalar part `w` and vector part `(x, y, z)`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quaternion<T> {
+    /// Scalar part
+    pub w: T,
+    /// First component of the vector part
+    pub x: T,
+    /// Second component of the vector part
+    pub y: T,
+    /// Third component of the vector part
+    pub z: T,
+}
+
+impl<T> Quaternion<T> {
+    /// Constructs a quaternion from its four components
+    #[inline(always)]
+    pub fn new(w: T, x: T, y: T, z: T) -> Quaternion<T> {
+        Quaternion { w, x, y, z }
+    }
+}
+
+impl<T> Quaternion<T>
+    where T: Copy
+{
+    /// Returns the vector part `[x, y, z]`
+    #[inline(always)]
+    pub fn vector(&self) -> [T; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl<T> From<(T, [T; 3])> for Quaternion<T>
+    where T: Copy
+{
+    #[inline(always)]
+    fn from(q: (T, [T; 3])) -> Quaternion<T> {
+        Quaternion::new(q.0, q.1[0], q.1[1], q.1[2])
+    }
+}
+
+impl<T> From<Quaternion<T>> for (T, [T; 3]) {
+    #[inline(always)]
+    fn from(q: Quaternion<T>) -> (T, [T; 3]) {
+        (q.w, [q.x, q.y, q.z])
+    }
+}
+
+impl<T> PartialEq<(T, [T; 3])> for Quaternion<T>
+    where T: PartialEq
+{
+    #[inline(always)]
+    fn eq(&self, q: &(T, [T; 3])) -> bool {
+        self.w == q.0 && self.x == q.1[0] && self.y == q.1[1] && self.z == q.1[2]
+    }
+}
+
+impl<T> Index<usize> for Quaternion<T> {
+    type Output = T;
+
+    /// Index 0 is the scalar part, indices 1 to 3 are the vector part
+    #[inline(always)]
+    fn index(&self, i: usize) -> &T {
+        match i {
+            0 => &self.w,
+            1 => &self.x,
+            2 => &self.y,
+            3 => &self.z,
+            _ => panic!("quaternion index out of bounds: {}", i),
+        }
+    }
+}
+
+impl<T> IndexMut<usize> for Quaternion<T> {
+    #[inline(always)]
+    fn index_mut(&mut self, i: usize) -> &mut T {
+        match i {
+            0 => &mut self.w,
+            1 => &mut self.x,
+            2 => &mut self.y,
+            3 => &mut self.z,
+            _ => panic!("quaternion index out of bounds: {}", i),
+        }
+    }
+}
+
+impl<T> Add for Quaternion<T>
+    where T: Float
+{
+    type Output = Quaternion<T>;
+
+    #[inline(always)]
+    fn add(self, b: Quaternion<T>) -> Quaternion<T> {
+        Quaternion::new(self.w + b.w, self.x + b.x, self.y + b.y, self.z + b.z)
+    }
+}
+
+impl<T> Sub for Quaternion<T>
+    where T: Float
+{
+    type Output = Quaternion<T>;
+
+    #[inline(always)]
+    fn sub(self, b: Quaternion<T>) -> Quaternion<T> {
+        Quaternion::new(self.w - b.w, self.x - b.x, self.y - b.y, self.z - b.z)
+    }
+}
+
+impl<T> Mul for Quaternion<T>
+    where T: Float
+{
+    type Output = Quaternion<T>;
+
+    /// Hamilton product
+    #[inline(always)]
+    fn mul(self, b: Quaternion<T>) -> Quaternion<T> {
+        let a = self;
+        Quaternion::new(
+            a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w
+        )
+    }
+}
+
+impl<T> Mul<T> for Quaternion<T>
+    where T: Float
+{
+    type Output = Quaternion<T>;
+
+    #[inline(always)]
+    fn mul(self, t: T) -> Quaternion<T> {
+        Quaternion::new(self.w * t, self.x * t, self.y * t, self.z * t)
+    }
+}
+
+impl<T> Div<T> for Quaternion<T>
+    where T: Float
+{
+    type Output = Quaternion<T>;
+
+    #[inline(always)]
+    fn div(self, t: T) -> Quaternion<T> {
+        Quaternion::new(self.w / t, self.x / t, self.y / t, self.z / t)
+    }
+}
+
+impl<T> Div for Quaternion<T>
+    where T: Float
+{
+    type Output = Quaternion<T>;
+
+    /// Right division `a * b^-1`
+    ///
+    /// Like float division this yields non-finite components when `b` is zero;
+    /// use `div_right` for a checked version.
+    #[inline(always)]
+    fn div(self, b: Quaternion<T>) -> Quaternion<T> {
+        self * conj(b) / square_len(b)
+    }
+}
+
+impl<T> Neg for Quaternion<T>
+    where T: Float
+{
+    type Output = Quaternion<T>;
+
+    #[inline(always)]
+    fn neg(self) -> Quaternion<T> {
+        Quaternion::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+impl<T> AddAssign for Quaternion<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn add_assign(&mut self, b: Quaternion<T>) {
+        *self = *self + b;
+    }
+}
+
+impl<T> SubAssign for Quaternion<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn sub_assign(&mut self, b: Quaternion<T>) {
+        *self = *self - b;
+    }
+}
+
+impl<T> MulAssign for Quaternion<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn mul_assign(&mut self, b: Quaternion<T>) {
+        *self = *self * b;
+    }
+}
+
+impl<T> MulAssign<T> for Quaternion<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn mul_assign(&mut self, t: T) {
+        *self = *self * t;
+    }
+}
+
+impl<T> DivAssign<T> for Quaternion<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn div_assign(&mut self, t: T) {
+        *self = *self / t;
+    }
+}
+
+macro_rules! impl_scalar_lhs_mul {
+    ($t:ty) => {
+        impl Mul<Quaternion<$t>> for $t {
+            type Output = Quaternion<$t>;
+
+            #[inline(always)]
+            fn mul(self, q: Quaternion<$t>) -> Quaternion<$t> {
+                q * self
+            }
+        }
+    }
+}
+
+impl_scalar_lhs_mul!(f32);
+impl_scalar_lhs_mul!(f64);
+
+
+/// `sin(x) / x`, using its Taylor series near zero
+#[inline(always)]
+fn sinc<T>(x: T) -> T
+    where T: Float
+{
+    let x2 = x * x;
+    if x2 < T::from_f64(1e-8) {
+        T::one() - x2 / T::from_f64(6.0)
+    } else {
+        x.sin() / x
+    }
+}
+
+/// Real exponential, which `Float` does not provide directly
+#[inline(always)]
+fn real_exp<T>(x: T) -> T
+    where T: Float
+{
+    T::from_f64(std::f64::consts::E).powf(x)
+}
+
+/// Real natural logarithm of a positive number, via `asinh`
+#[inline(always)]
+fn real_ln<T>(x: T) -> T
+    where T: Float
+{
+    let half = T::from_f64(0.5);
+    ((x - T::one() / x) * half).asinh()
+}
 
 /// Quaternion identity quaternion
 #[inline(always)]
@@ -22,88 +309,243 @@ pub fn id<T>() -> Quaternion<T>
 {
     let one = T::one();
     let zero = T::zero();
-    (one, [zero, zero, zero])
+    Quaternion::new(one, zero, zero, zero)
 }
 
 /// Adds two quaternions.
 #[inline(always)]
-pub fn add<T>(a: Quaternion<T>, b: Quaternion<T>) -> Quaternion<T>
-    where T: Float
+pub fn add<T, A, B>(a: A, b: B) -> Quaternion<T>
+    where T: Float, A: Into<Quaternion<T>>, B: Into<Quaternion<T>>
 {
-    let mut c: Quaternion<T> = id();
-    c.0 = a.0 + b.0;
-    c.1[0] = a.1[0] + b.1[0];
-    c.1[1] = a.1[1] + b.1[1];
-    c.1[2] = a.1[2] + b.1[2];
-    c
+    a.into() + b.into()
 }
 
 /// Scales a quaternion (element-wise) by a scalar
 #[inline(always)]
-pub fn scale<T>(q: Quaternion<T>, t: T) -> Quaternion<T>
-    where T: Float
+pub fn scale<T, Q>(q: Q, t: T) -> Quaternion<T>
+    where T: Float, Q: Into<Quaternion<T>>
 {
-    (q.0 * t, [q.1[0] * t, q.1[1] * t, q.1[2] * t])
+    q.into() * t
 }
 
 /// Dot product of two quaternions
 #[inline(always)]
-pub fn dot<T>(a: Quaternion<T>, b: Quaternion<T>) -> T
-    where T: Float
+pub fn dot<T, A, B>(a: A, b: B) -> T
+    where T: Float, A: Into<Quaternion<T>>, B: Into<Quaternion<T>>
 {
-    let v_total = a.1[0] * b.1[0] + a.1[1] * b.1[1] + a.1[2] * b.1[2];
-    a.0 * b.0 + v_total
+    let (a, b) = (a.into(), b.into());
+    a.w * b.w + a.x * b.x + a.y * b.y + a.z * b.z
 }
 
 /// Multiplies two quaternions
 #[inline(always)]
-pub fn mul<T>(a: Quaternion<T>, b: Quaternion<T>) -> Quaternion<T>
-    where T: Float
+pub fn mul<T, A, B>(a: A, b: B) -> Quaternion<T>
+    where T: Float, A: Into<Quaternion<T>>, B: Into<Quaternion<T>>
 {
-    let mut c: Quaternion<T> = id();
-    c.0 = a.0 * b.0 - a.1[0] * b.1[0] - a.1[1] * b.1[1] - a.1[2] * b.1[2];
-    c.1[0] = a.0 * b.1[0] + a.1[0] * b.0 + a.1[1] * b.1[2] - a.1[2] * b.1[1];
-    c.1[1] = a.0 * b.1[1] - a.1[0] * b.1[2] + a.1[1] * b.0 + a.1[2] * b.1[0];
-    c.1[2] = a.0 * b.1[2] + a.1[0] * b.1[1] - a.1[1] * b.1[0] + a.1[2] * b.0;
-    c
+    a.into() * b.into()
 }
 
 /// Takes the quaternion conjugate
 #[inline(always)]
-pub fn conj<T>(a: Quaternion<T>) -> Quaternion<T>
-    where T: Float
+pub fn conj<T, Q>(a: Q) -> Quaternion<T>
+    where T: Float, Q: Into<Quaternion<T>>
 {
-    (a.0, [-a.1[0], -a.1[1], -a.1[2]])
+    let a = a.into();
+    Quaternion::new(a.w, -a.x, -a.y, -a.z)
 }
 
 /// Computes the square length of a quaternion.
 #[inline(always)]
-pub fn square_len<T>(q: Quaternion<T>) -> T
-    where T: Float
+pub fn square_len<T, Q>(q: Q) -> T
+    where T: Float, Q: Into<Quaternion<T>>
 {
-    q.0 * q.0 + q.1[0] * q.1[0] + q.1[1] * q.1[1] + q.1[2] * q.1[2]
+    let q = q.into();
+    dot(q, q)
 }
 
 /// Computes the length of a quarternion
 #[inline(always)]
-pub fn len<T>(q: Quaternion<T>) -> T
-    where T: Float
+pub fn len<T, Q>(q: Q) -> T
+    where T: Float, Q: Into<Quaternion<T>>
 {
     square_len(q).sqrt()
 }
 
-/// Rotate the given vector using the given quaternion
+/// Normalizes a quaternion to unit length
+///
+/// A zero quaternion is returned unchanged instead of becoming NaN.
+#[inline(always)]
+pub fn normalize<T>(q: Quaternion<T>) -> Quaternion<T>
+    where T: Float
+{
+    let l = len(q);
+    if l > T::zero() { q / l } else { q }
+}
+
+/// Normalizes a quaternion, returning `None` if its length is at most `min_len`
+#[inline(always)]
+pub fn try_normalize<T>(q: Quaternion<T>, min_len: T) -> Option<Quaternion<T>>
+    where T: Float
+{
+    let l = len(q);
+    if l > min_len { Some(q / l) } else { None }
+}
+
+/// Multiplicative inverse (reciprocal) of a quaternion
+///
+/// Returns `None` for the zero quaternion, which has no inverse.
+#[inline(always)]
+pub fn inverse<T>(q: Quaternion<T>) -> Option<Quaternion<T>>
+    where T: Float
+{
+    let sq = square_len(q);
+    if sq > T::zero() { Some(conj(q) / sq) } else { None }
+}
+
+/// Left division `b^-1 * a`, returning `None` if `b` is zero
+#[inline(always)]
+pub fn div_left<T>(a: Quaternion<T>, b: Quaternion<T>) -> Option<Quaternion<T>>
+    where T: Float
+{
+    inverse(b).map(|b_inv| mul(b_inv, a))
+}
+
+/// Right division `a * b^-1`, returning `None` if `b` is zero
+#[inline(always)]
+pub fn div_right<T>(a: Quaternion<T>, b: Quaternion<T>) -> Option<Quaternion<T>>
+    where T: Float
+{
+    inverse(b).map(|b_inv| mul(a, b_inv))
+}
+
+/// Normalized linear interpolation between two unit quaternions
+///
+/// Takes the shortest path and does not clamp `t`.
+#[inline(always)]
+pub fn nlerp<T>(a: Quaternion<T>, b: Quaternion<T>, t: T) -> Quaternion<T>
+    where T: Float
+{
+    let b = if dot(a, b) < T::zero() { -b } else { b };
+    normalize(a * (T::one() - t) + b * t)
+}
+
+/// Spherical linear interpolation between two unit quaternions
+///
+/// `t` is clamped to `[0, 1]`; see `slerp_unclamped` for extrapolation.
+#[inline(always)]
+pub fn slerp<T>(a: Quaternion<T>, b: Quaternion<T>, t: T) -> Quaternion<T>
+    where T: Float
+{
+    slerp_unclamped(a, b, t.max(T::zero()).min(T::one()))
+}
+
+/// Spherical linear interpolation that extrapolates for `t` outside `[0, 1]`
+///
+/// Takes the shortest path, and falls back to `nlerp` when the quaternions are
+/// too close for `sin` of the angle between them to be divided by safely.
+#[inline(always)]
+pub fn slerp_unclamped<T>(a: Quaternion<T>, b: Quaternion<T>, t: T) -> Quaternion<T>
+    where T: Float
+{
+    let one = T::one();
+
+    let mut d = dot(a, b);
+    let mut b = b;
+    if d < T::zero() {
+        // q and -q are the same rotation, go the short way around
+        b = -b;
+        d = -d;
+    }
+
+    if d > T::from_f64(0.9995) {
+        return nlerp(a, b, t);
+    }
+
+    let theta = d.acos();
+    let sin_theta = theta.sin();
+    let wa = ((one - t) * theta).sin() / sin_theta;
+    let wb = (t * theta).sin() / sin_theta;
+    a * wa + b * wb
+}
+
+/// Quaternion exponential
+#[inline(always)]
+pub fn exp<T>(q: Quaternion<T>) -> Quaternion<T>
+    where T: Float
+{
+    use vecmath::{vec3_len, vec3_scale};
+
+    let v = q.vector();
+    let theta = vec3_len(v);
+    let ew = real_exp(q.w);
+    (ew * theta.cos(), vec3_scale(v, ew * sinc(theta))).into()
+}
+
+/// Principal quaternion logarithm
+///
+/// Returns `None` for the zero quaternion. A negative real quaternion, whose
+/// rotation axis is ambiguous, has its imaginary part placed on the x axis.
+#[inline(always)]
+pub fn ln<T>(q: Quaternion<T>) -> Option<Quaternion<T>>
+    where T: Float
+{
+    use std::f64::consts::PI;
+    use vecmath::{vec3_len, vec3_scale};
+
+    let zero = T::zero();
+    let n = len(q);
+    if n <= zero {
+        return None;
+    }
+
+    let v = q.vector();
+    let v_len = vec3_len(v);
+    let v = if v_len > zero {
+        vec3_scale(v, v_len.atan2(q.w) / v_len)
+    } else if q.w > zero {
+        [zero; 3]
+    } else {
+        [T::from_f64(PI), zero, zero]
+    };
+    Some((real_ln(n), v).into())
+}
+
+/// Raises a quaternion to a real power, `exp(t * ln(q))`
+///
+/// The zero quaternion is returned unchanged.
+#[inline(always)]
+pub fn pow<T>(q: Quaternion<T>, t: T) -> Quaternion<T>
+    where T: Float
+{
+    match ln(q) {
+        Some(l) => exp(l * t),
+        None => q,
+    }
+}
+
+/// Principal square root of a quaternion
+#[inline(always)]
+pub fn sqrt<T>(q: Quaternion<T>) -> Quaternion<T>
+    where T: Float
+{
+    pow(q, T::from_f64(0.5))
+}
+
+/// Rotate the given vector using the given unit quaternion
 #[inline(always)]
-pub fn rotate_vector<T>(q: Quaternion<T>, v: [T; 3]) -> [T; 3]
+pub fn rotate_vector<T>(q: UnitQuaternion<T>, v: [T; 3]) -> [T; 3]
     where T: Float
 {
+    let q = q.into_inner();
     let zero = T::zero();
-    let v_as_q: Quaternion<T> = (zero, v);
+    let v_as_q: Quaternion<T> = (zero, v).into();
     let conj: Quaternion<T> = conj(q);
-    mul(mul(q, v_as_q), conj).1
+    mul(mul(q, v_as_q), conj).vector()
 }
 
 /// Constructs a quaternion for a given angle
+///
+/// `v` must be a unit vector; see `UnitQuaternion::from_axis_angle` otherwise.
 #[inline(always)]
 pub fn axis_angle<T>(v: [T; 3], theta: T) -> Quaternion<T>
     where T: Float + Debug
@@ -112,13 +554,73 @@ pub fn axis_angle<T>(v: [T; 3], theta: T) -> Quaternion<T>
 
     let two = T::one() + T::one();
     let half_theta = theta / two;
-    (half_theta.cos(), scale(v, half_theta.sin()))
+    (half_theta.cos(), scale(v, half_theta.sin())).into()
+}
+
+/// Extracts the unit axis and angle of a rotation
+///
+/// The angle is in `[0, pi]`, with the axis flipped as needed. Near the
+/// identity, where the axis is undefined, the x axis is returned.
+#[inline(always)]
+pub fn to_axis_angle<T>(q: UnitQuaternion<T>) -> ([T; 3], T)
+    where T: Float
+{
+    use vecmath::{vec3_len, vec3_scale};
+
+    let zero = T::zero();
+    let two = T::one() + T::one();
+    // q and -q are the same rotation, pick the one with w >= 0
+    let q = if q.w < zero { -q.into_inner() } else { q.into_inner() };
+
+    let v = q.vector();
+    let v_len = vec3_len(v);
+    let angle = two * v_len.atan2(q.w);
+    if v_len > T::from_f64(1e-12) {
+        (vec3_scale(v, T::one() / v_len), angle)
+    } else {
+        ([T::one(), zero, zero], angle)
+    }
+}
+
+/// Unit quaternion of a rotation vector, whose direction is the axis and norm the angle
+#[inline(always)]
+pub fn from_rotation_vector<T>(r: [T; 3]) -> UnitQuaternion<T>
+    where T: Float
+{
+    use vecmath::{vec3_len, vec3_scale};
+
+    // sinc switches to its series for small angles, so no normalize is needed
+    let half_theta = vec3_len(r) * T::from_f64(0.5);
+    let q = (half_theta.cos(), vec3_scale(r, T::from_f64(0.5) * sinc(half_theta)));
+    UnitQuaternion::new_unchecked(q.into())
+}
+
+/// Rotation vector of a unit quaternion, with norm in `[0, pi]`
+#[inline(always)]
+pub fn to_rotation_vector<T>(q: UnitQuaternion<T>) -> [T; 3]
+    where T: Float
+{
+    use vecmath::{vec3_scale, vec3_square_len};
+
+    let two = T::one() + T::one();
+    let q = if q.w < T::zero() { -q.into_inner() } else { q.into_inner() };
+
+    let v = q.vector();
+    let s2 = vec3_square_len(v);
+    // 2 * atan2(s, w) / s, with the series of atan near zero
+    let factor = if s2 < T::from_f64(1e-8) {
+        two / q.w * (T::one() - s2 / (T::from_f64(3.0) * q.w * q.w))
+    } else {
+        let s = s2.sqrt();
+        two * s.atan2(q.w) / s
+    };
+    vec3_scale(v, factor)
 }
 
 
 /// Construct a quaternion representing the rotation from a to b
 #[inline(always)]
-pub fn rotation_from_to<T>(a: [T; 3], b: [T; 3]) -> Quaternion<T>
+pub fn rotation_from_to<T>(a: [T; 3], b: [T; 3]) -> UnitQuaternion<T>
     where T: Float + Debug
 {
     use std::f64::consts::PI;
@@ -133,7 +635,7 @@ pub fn rotation_from_to<T>(a: [T; 3], b: [T; 3]) -> Quaternion<T>
     
     if dot >= one {
         // a and b are parallel
-        return id();
+        return UnitQuaternion::identity();
     }
     
     if dot < T::from_f64(-0.999999) {
@@ -142,29 +644,182 @@ pub fn rotation_from_to<T>(a: [T; 3], b: [T; 3]) -> Quaternion<T>
             axis = vec3_cross([zero, one, zero], a);
         }
         axis = vec3_normalized(axis);
-        axis_angle(axis, T::from_f64(PI))
+        UnitQuaternion::new_unchecked(axis_angle(axis, T::from_f64(PI)))
     } else {
-        let q = (
+        let q: Quaternion<T> = (
             one + dot,
             vec3_cross(a,b)
-        );
-        scale(q, one / len(q))
+        ).into();
+        UnitQuaternion::new_unchecked(scale(q, one / len(q)))
     }
 }
 
 
+/// Splits a rotation into a swing and a twist about `axis`, with `q = swing * twist`
+///
+/// The twist rotates about `axis` and the swing about an axis perpendicular to
+/// it. When `q` is a half turn about an axis orthogonal to `axis` the twist is
+/// undefined and the identity is used, leaving all of `q` in the swing.
+pub fn swing_twist<T>(q: UnitQuaternion<T>, axis: [T; 3])
+    -> (UnitQuaternion<T>, UnitQuaternion<T>)
+    where T: Float
+{
+    use vecmath::{vec3_dot, vec3_normalized, vec3_scale};
+
+    let axis = vec3_normalized(axis);
+    let p = vec3_scale(axis, vec3_dot(q.vector(), axis));
+    let twist = match try_normalize((q.w, p).into(), T::from_f64(1e-9)) {
+        Some(t) => UnitQuaternion::new_unchecked(t),
+        None => UnitQuaternion::identity(),
+    };
+    (q * twist.inverse(), twist)
+}
+
+
+/// Row major rotation matrix of a unit quaternion
+///
+/// The layout matches vecmath's `row_mat3_*` functions, so
+/// `row_mat3_transform(to_mat3(q), v)` equals `rotate_vector(q, v)`.
+#[inline(always)]
+pub fn to_mat3<T>(q: UnitQuaternion<T>) -> Matrix3<T>
+    where T: Float
+{
+    let one = T::one();
+    let two = one + one;
+    let Quaternion { w, x, y, z } = q.into_inner();
+
+    [
+        [one - two * (y * y + z * z), two * (x * y - w * z), two * (x * z + w * y)],
+        [two * (x * y + w * z), one - two * (x * x + z * z), two * (y * z - w * x)],
+        [two * (x * z - w * y), two * (y * z + w * x), one - two * (x * x + y * y)],
+    ]
+}
+
+/// Column major rotation matrix of a unit quaternion, for vecmath's `col_mat3_*`
+#[inline(always)]
+pub fn to_col_mat3<T>(q: UnitQuaternion<T>) -> Matrix3<T>
+    where T: Float
+{
+    mat3_transposed(to_mat3(q))
+}
+
+/// Row major homogeneous rotation matrix of a unit quaternion
+#[inline(always)]
+pub fn to_mat4<T>(q: UnitQuaternion<T>) -> Matrix4<T>
+    where T: Float
+{
+    let zero = T::zero();
+    let m = to_mat3(q);
+
+    [
+        [m[0][0], m[0][1], m[0][2], zero],
+        [m[1][0], m[1][1], m[1][2], zero],
+        [m[2][0], m[2][1], m[2][2], zero],
+        [zero, zero, zero, T::one()],
+    ]
+}
+
+/// Column major homogeneous rotation matrix of a unit quaternion, for vecmath's `col_mat4_*`
+#[inline(always)]
+pub fn to_col_mat4<T>(q: UnitQuaternion<T>) -> Matrix4<T>
+    where T: Float
+{
+    mat4_transposed(to_mat4(q))
+}
+
+/// Unit quaternion of a row major rotation matrix
+///
+/// Uses Shepperd's method, solving for whichever component the largest of
+/// the trace and diagonal elements makes best conditioned. The result is
+/// renormalized so slightly non-orthogonal input still gives a rotation.
+pub fn from_mat3<T>(m: Matrix3<T>) -> UnitQuaternion<T>
+    where T: Float
+{
+    let one = T::one();
+    let quarter = T::from_f64(0.25);
+
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let q = if trace >= m[0][0] && trace >= m[1][1] && trace >= m[2][2] {
+        let s = (one + trace).sqrt() * (one + one);
+        Quaternion::new(quarter * s,
+                        (m[2][1] - m[1][2]) / s,
+                        (m[0][2] - m[2][0]) / s,
+                        (m[1][0] - m[0][1]) / s)
+    } else if m[0][0] >= m[1][1] && m[0][0] >= m[2][2] {
+        let s = (one + m[0][0] - m[1][1] - m[2][2]).sqrt() * (one + one);
+        Quaternion::new((m[2][1] - m[1][2]) / s,
+                        quarter * s,
+                        (m[0][1] + m[1][0]) / s,
+                        (m[0][2] + m[2][0]) / s)
+    } else if m[1][1] >= m[2][2] {
+        let s = (one - m[0][0] + m[1][1] - m[2][2]).sqrt() * (one + one);
+        Quaternion::new((m[0][2] - m[2][0]) / s,
+                        (m[0][1] + m[1][0]) / s,
+                        quarter * s,
+                        (m[1][2] + m[2][1]) / s)
+    } else {
+        let s = (one - m[0][0] - m[1][1] + m[2][2]).sqrt() * (one + one);
+        Quaternion::new((m[1][0] - m[0][1]) / s,
+                        (m[0][2] + m[2][0]) / s,
+                        (m[1][2] + m[2][1]) / s,
+                        quarter * s)
+    };
+    UnitQuaternion::new_unchecked(normalize(q))
+}
+
+/// Unit quaternion of a column major rotation matrix
+#[inline(always)]
+pub fn from_col_mat3<T>(m: Matrix3<T>) -> UnitQuaternion<T>
+    where T: Float
+{
+    from_mat3(mat3_transposed(m))
+}
+
+
+/// Helpers shared by the tests of every module
+#[cfg(test)]
+mod test_util {
+    use UnitQuaternion;
+
+    /// Rotation by `theta` radians about `axis`
+    pub fn rot(axis: [f64; 3], theta: f64) -> UnitQuaternion<f64> {
+        UnitQuaternion::from_axis_angle(axis, theta)
+    }
+
+    /// Angle of the rotation taking `a` to `b`, whatever their signs
+    pub fn angle_between(a: UnitQuaternion<f64>, b: UnitQuaternion<f64>) -> f64 {
+        (a.inverse() * b).angle()
+    }
+
+    /// Asserts that `a` and `b` rotate within `tol` radians of each other
+    #[track_caller]
+    pub fn assert_same_rotation(a: UnitQuaternion<f64>, b: UnitQuaternion<f64>, tol: f64) {
+        let angle = angle_between(a, b);
+        assert!(angle < tol, "{:?} and {:?} are {} rad apart", a, b, angle);
+    }
+
+    /// Asserts that every component of `a` is within `tol` of `b`
+    #[track_caller]
+    pub fn assert_close(a: [f64; 3], b: [f64; 3], tol: f64) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < tol, "{:?} != {:?}", a, b);
+        }
+    }
+}
+
 /// Tests
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::f32::consts::PI;
+    use test_util::{assert_close, assert_same_rotation, rot};
     
     static EPSILON: f32 = 0.00001;
 
     #[test]
     fn test_add() {
         let q0: Quaternion<f64> = id();
-        let q1: Quaternion<f64> = (1.0, [1.0, 1.0, 1.0]);
+        let q1 = (1.0, [1.0, 1.0, 1.0]);
         assert_eq!(add(q0, q1), (2.0, [1.0, 1.0, 1.0]));
     }
 
@@ -189,30 +844,187 @@ mod tests {
         use vecmath::vec3_dot as dot;
         use vecmath::vec3_scale as scale;
 
-        let q0: Quaternion<f64> = (2.0, [1.0, 1.0, 1.0]);
-        let q1: Quaternion<f64> = (3.0, [1.0, 1.0, 1.0]);
+        let q0: (f64, [f64; 3]) = (2.0, [1.0, 1.0, 1.0]);
+        let q1: (f64, [f64; 3]) = (3.0, [1.0, 1.0, 1.0]);
 
-        let q: Quaternion<f64> = (q0.0 * q1.0 - dot(q0.1, q1.1),
+        let q: (f64, [f64; 3]) = (q0.0 * q1.0 - dot(q0.1, q1.1),
                                   add(cross(q0.1, q1.1),
                                       add(scale(q1.1, q0.0), scale(q0.1, q1.0))));
-        assert_eq!(q, mul(q0, q1));
+        assert_eq!(mul(q0, q1), q);
     }
 
     #[test]
     fn test_conj() {
-        let q: Quaternion<f64> = (2.0, [1.0, 1.0, 1.0]);
-        let q_conj: Quaternion<f64> = (2.0, [-1.0, -1.0, -1.0]);
+        let q = (2.0, [1.0, 1.0, 1.0]);
+        let q_conj = (2.0, [-1.0, -1.0, -1.0]);
+
+        assert_eq!(conj(q), q_conj);
+    }
+
+    #[test]
+    fn test_operators() {
+        let a: Quaternion<f64> = Quaternion::new(1.0, 2.0, 3.0, 4.0);
+        let b: Quaternion<f64> = Quaternion::new(-2.0, 0.5, 1.0, 3.0);
+
+        assert_eq!(a + b, add(a, b));
+        assert_eq!(a - b, add(a, -b));
+        assert_eq!(a * b, mul(a, b));
+        assert_eq!(a * 2.0, scale(a, 2.0));
+        assert_eq!(2.0 * a, scale(a, 2.0));
+        assert_eq!(a / 2.0, scale(a, 0.5));
+
+        let mut c = a;
+        c += b;
+        c -= b;
+        c *= b;
+        assert_eq!(c, a * b);
+        c *= 2.0;
+        c /= 2.0;
+        assert_eq!(c, a * b);
+    }
+
+    #[test]
+    fn test_index() {
+        let mut q: Quaternion<f32> = Quaternion::new(1.0, 2.0, 3.0, 4.0);
+        assert_eq!([q[0], q[1], q[2], q[3]], [1.0, 2.0, 3.0, 4.0]);
+        q[2] = 5.0;
+        assert_eq!(q.y, 5.0);
+    }
 
-        assert_eq!(q_conj, conj(q));
+    #[test]
+    fn test_tuple_conversion() {
+        let q: Quaternion<f64> = (1.0, [2.0, 3.0, 4.0]).into();
+        assert_eq!(q, Quaternion::new(1.0, 2.0, 3.0, 4.0));
+        let t: (f64, [f64; 3]) = q.into();
+        assert_eq!(t, (1.0, [2.0, 3.0, 4.0]));
     }
 
     #[test]
     fn test_square_len() {
         use vecmath::vec3_square_len;
-        let q: Quaternion<f64> = (2.0, [1.0, 1.0, 1.0]);
+        let q: (f64, [f64; 3]) = (2.0, [1.0, 1.0, 1.0]);
         assert_eq!(q.0 * q.0 + vec3_square_len(q.1), square_len(q));
     }
 
+    #[test]
+    fn test_normalize() {
+        let q: Quaternion<f64> = Quaternion::new(0.0, 3.0, 0.0, 4.0);
+        assert_eq!(normalize(q), Quaternion::new(0.0, 0.6, 0.0, 0.8));
+
+        let zero: Quaternion<f64> = Quaternion::default();
+        assert_eq!(normalize(zero), zero);
+        assert_eq!(try_normalize(zero, 0.0), None);
+        assert_eq!(try_normalize(Quaternion::new(1e-9, 0.0, 0.0, 0.0), 1e-6), None);
+        assert_eq!(try_normalize(q, 1e-6), Some(normalize(q)));
+    }
+
+    #[test]
+    fn test_inverse() {
+        let q: Quaternion<f64> = Quaternion::new(1.0, 2.0, -1.0, 0.5);
+        let r = mul(q, inverse(q).unwrap());
+        assert!((r.w - 1.0).abs() < EPSILON as f64);
+        assert!(square_len(r - id()) < EPSILON as f64);
+
+        assert_eq!(inverse(Quaternion::<f64>::default()), None);
+    }
+
+    #[test]
+    fn test_division() {
+        let a: Quaternion<f64> = Quaternion::new(1.0, 2.0, 3.0, 4.0);
+        let b: Quaternion<f64> = Quaternion::new(0.5, -1.0, 2.0, 1.0);
+
+        let right = div_right(a, b).unwrap();
+        let left = div_left(a, b).unwrap();
+        assert!(square_len(mul(right, b) - a) < EPSILON as f64);
+        assert!(square_len(mul(b, left) - a) < EPSILON as f64);
+        assert!(square_len(a / b - right) < EPSILON as f64);
+        assert_eq!(div_right(a, Quaternion::default()), None);
+    }
+
+    #[test]
+    fn test_slerp() {
+        use std::f64::consts::PI;
+
+        let a = rot([0.0, 0.0, 1.0], 0.0);
+        let b = rot([0.0, 0.0, 1.0], PI / 2.0);
+        let h = 0.5f64.sqrt();
+
+        let q = UnitQuaternion::new_unchecked(slerp(*a, *b, 0.5));
+        assert_close(rotate_vector(q, [1.0, 0.0, 0.0]), [h, h, 0.0], EPSILON as f64);
+
+        // b and -b are the same rotation, so the path should not change
+        let q = UnitQuaternion::new_unchecked(slerp(*a, -*b, 0.5));
+        assert_close(rotate_vector(q, [1.0, 0.0, 0.0]), [h, h, 0.0], EPSILON as f64);
+
+        // t is clamped
+        assert_eq!(slerp(*a, *b, 2.0), slerp(*a, *b, 1.0));
+    }
+
+    #[test]
+    fn test_slerp_small_angle() {
+        let a = rot([1.0, 0.0, 0.0], 0.3);
+        let b = rot([1.0, 0.0, 0.0], 0.3 + 1e-5);
+        let q = slerp(*a, *b, 0.5);
+        assert!((square_len(q) - 1.0).abs() < EPSILON as f64);
+        assert!(square_len(q - *rot([1.0, 0.0, 0.0], 0.3 + 5e-6)) <
+                EPSILON as f64);
+    }
+
+    #[test]
+    fn test_slerp_unclamped() {
+        use std::f64::consts::PI;
+
+        let a = rot([0.0, 0.0, 1.0], 0.0);
+        let b = rot([0.0, 0.0, 1.0], PI / 4.0);
+        let q = UnitQuaternion::new_unchecked(slerp_unclamped(*a, *b, 2.0));
+        assert_close(rotate_vector(q, [1.0, 0.0, 0.0]), [0.0, 1.0, 0.0], EPSILON as f64);
+    }
+
+    #[test]
+    fn test_nlerp() {
+        use std::f64::consts::PI;
+
+        let a = rot([0.0, 1.0, 0.0], 0.0);
+        let b = rot([0.0, 1.0, 0.0], PI / 2.0);
+        let h = 0.5f64.sqrt();
+
+        // symmetric interpolation hits the true midpoint
+        let q = UnitQuaternion::new_unchecked(nlerp(*a, *b, 0.5));
+        assert_close(rotate_vector(q, [1.0, 0.0, 0.0]), [h, 0.0, -h], EPSILON as f64);
+        assert!(square_len(nlerp(*a, *b, 1.0) - *b) < EPSILON as f64);
+    }
+
+    #[test]
+    fn test_exp_ln() {
+        let q: Quaternion<f64> = Quaternion::new(0.5, -1.0, 0.25, 2.0);
+        assert!(square_len(exp(ln(q).unwrap()) - q) < EPSILON as f64);
+
+        let p: Quaternion<f64> = Quaternion::new(0.3, 0.4, -0.2, 1.1);
+        assert!(square_len(ln(exp(p)).unwrap() - p) < EPSILON as f64);
+
+        assert_eq!(exp(Quaternion::<f64>::default()), id());
+        assert_eq!(ln(Quaternion::<f64>::default()), None);
+
+        // e^(pi * i) = -1
+        let minus_one: Quaternion<f64> = Quaternion::new(-1.0, 0.0, 0.0, 0.0);
+        let l = ln(minus_one).unwrap();
+        assert!(square_len(exp(l) - minus_one) < EPSILON as f64);
+    }
+
+    #[test]
+    fn test_pow_sqrt() {
+        let q: Quaternion<f64> = Quaternion::new(1.5, -1.0, 0.5, 2.0);
+        assert!(square_len(pow(q, 2.0) - mul(q, q)) < EPSILON as f64);
+        assert!(square_len(pow(q, 3.0) - mul(q, mul(q, q))) < EPSILON as f64);
+
+        let r = sqrt(q);
+        assert!(square_len(mul(r, r) - q) < EPSILON as f64);
+        assert!(r.w > 0.0);
+
+        let half = pow(*rot([1.0, 1.0, 0.0], 1.2), 0.5);
+        assert!(square_len(half - *rot([1.0, 1.0, 0.0], 0.6)) < EPSILON as f64);
+    }
+
     #[test]
     fn test_axis_angle() {
         use vecmath::Vector3;
@@ -225,6 +1037,119 @@ mod tests {
         assert!((square_len(q) - 1.0).abs() < EPSILON);
     }   
     
+    #[test]
+    fn test_swing_twist() {
+        use vecmath::vec3_dot;
+
+        let twist_in = rot([0.0, 0.0, 1.0], 0.8);
+        let swing_in = rot([1.0, 1.0, 0.0], 0.5);
+        let q = swing_in * twist_in;
+
+        let (swing, twist) = swing_twist(q, [0.0, 0.0, 2.0]);
+        assert!(square_len(*(swing * twist) - *q) < EPSILON as f64);
+        assert_same_rotation(twist, twist_in, 1e-12);
+        assert_same_rotation(swing, swing_in, 1e-12);
+        assert!(vec3_dot(swing.vector(), [0.0, 0.0, 1.0]).abs() < EPSILON as f64);
+    }
+
+    #[test]
+    fn test_swing_twist_degenerate() {
+        use std::f64::consts::PI;
+
+        // a half turn about x has no component about z
+        let q = rot([1.0, 0.0, 0.0], PI);
+        let (swing, twist) = swing_twist(q, [0.0, 0.0, 1.0]);
+        assert_eq!(twist, UnitQuaternion::identity());
+        assert_eq!(swing, q);
+    }
+
+    #[test]
+    fn test_to_mat() {
+        use vecmath::{row_mat3_transform, col_mat3_transform, row_mat4_transform,
+                      col_mat4_transform};
+
+        let q = rot([1.0, -2.0, 0.5], 1.3);
+        let v = [0.3, 2.0, -1.0];
+        let expected = rotate_vector(q, v);
+
+        assert_close(row_mat3_transform(to_mat3(q), v), expected, EPSILON as f64);
+        assert_close(col_mat3_transform(to_col_mat3(q), v), expected, EPSILON as f64);
+
+        let r = row_mat4_transform(to_mat4(q), [v[0], v[1], v[2], 1.0]);
+        assert_close([r[0], r[1], r[2]], expected, EPSILON as f64);
+        assert_eq!(r[3], 1.0);
+        let r = col_mat4_transform(to_col_mat4(q), [v[0], v[1], v[2], 0.0]);
+        assert_close([r[0], r[1], r[2]], expected, EPSILON as f64);
+    }
+
+    #[test]
+    fn test_from_mat3() {
+        use std::f64::consts::PI;
+
+        // exercise every pivot of Shepperd's method, including half turns
+        let rotations = [
+            rot([0.2, 0.3, 1.0], 0.4),
+            rot([1.0, 0.0, 0.0], PI),
+            rot([0.0, 1.0, 0.0], PI),
+            rot([0.0, 0.0, 1.0], PI),
+            rot([1.0, 0.1, -0.2], 2.9),
+            rot([-0.1, 1.0, 0.3], 3.0),
+            rot([0.3, -0.2, 1.0], 2.8),
+        ];
+        for &q in rotations.iter() {
+            let r = from_mat3(to_mat3(q));
+            assert_same_rotation(r, q, 1e-12);
+            let r = from_col_mat3(to_col_mat3(q));
+            assert_same_rotation(r, q, 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_to_axis_angle() {
+        use std::f64::consts::PI;
+
+        let (axis, angle) = to_axis_angle(rot([0.0, 3.0, 4.0], 1.2));
+        assert_close(axis, [0.0, 0.6, 0.8], EPSILON as f64);
+        assert!((angle - 1.2).abs() < EPSILON as f64);
+
+        // angles past pi come back as the shorter rotation about the flipped axis
+        let (axis, angle) = to_axis_angle(rot([0.0, 0.0, 1.0], 1.5 * PI));
+        assert_close(axis, [0.0, 0.0, -1.0], EPSILON as f64);
+        assert!((angle - 0.5 * PI).abs() < EPSILON as f64);
+
+        let (axis, angle) = to_axis_angle(-rot([1.0, 0.0, 0.0], 0.5));
+        assert_close(axis, [1.0, 0.0, 0.0], EPSILON as f64);
+        assert!((angle - 0.5).abs() < EPSILON as f64);
+
+        let (axis, angle) = to_axis_angle(UnitQuaternion::<f64>::identity());
+        assert_eq!((axis, angle), ([1.0, 0.0, 0.0], 0.0));
+    }
+
+    #[test]
+    fn test_rotation_vector() {
+        use std::f64::consts::PI;
+
+        let r = [0.3, -1.2, 0.8];
+        let q = from_rotation_vector(r);
+        assert!(square_len(*q - *rot(r, vecmath::vec3_len(r))) < EPSILON as f64);
+        assert_close(to_rotation_vector(q), r, EPSILON as f64);
+
+        // the shorter of q and -q is chosen
+        let q = rot([0.0, 1.0, 0.0], 1.5 * PI);
+        assert_close(to_rotation_vector(q), [0.0, -0.5 * PI, 0.0], EPSILON as f64);
+
+        assert_eq!(to_rotation_vector(from_rotation_vector([0.0; 3])), [0.0; 3]);
+    }
+
+    #[test]
+    fn test_rotation_vector_small_angle() {
+        let r: [f32; 3] = [1e-6, -2e-6, 5e-7];
+        let back = to_rotation_vector(from_rotation_vector(r));
+        for i in 0..3 {
+            assert!((back[i] - r[i]).abs() < 1e-6 * r[i].abs());
+        }
+    }
+
     #[test]
     fn test_rotation_from_to_1() {
         use vecmath::Vector3;
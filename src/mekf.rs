@@ -0,0 +1,314 @@
+//! Multiplicative extended Kalman filter (MEKF) for attitude estimation
+//!
+//! The nominal state is a unit quaternion mapping body coordinates to the
+//! reference frame, plus a gyroscope bias. The filter's covariance describes
+//! a six element error state: a small rotation `dtheta` in the body frame,
+//! applied as `q * exp(dtheta / 2)`, and the bias error. After each update
+//! the error is folded back into the nominal state, so the quaternion never
+//! has to carry a covariance of its own. See Markley, "Attitude Error
+//! Representations for Kalman Filtering" (2003).
+
+use vecmath::traits::Float;
+use vecmath::{mat3_inv, vec3_add, vec3_normalized, vec3_scale, vec3_sub};
+
+use {UnitQuaternion, rotate_vector, to_mat3, from_rotation_vector};
+use kinematics::{self, Frame};
+use so3::hat;
+
+
+/// Covariance of the error state, ordered `[dtheta, dbias]`
+pub type Covariance<T> = [[T; 6]; 6];
+
+/// Continuous time noise densities driving the filter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProcessNoise<T> {
+    /// Gyroscope angle random walk, in rad / s / sqrt(Hz)
+    pub gyro: T,
+    /// Gyroscope bias random walk, in rad / s^2 / sqrt(Hz)
+    pub bias: T,
+}
+
+/// A direction known in the reference frame and observed in the body frame
+///
+/// Both directions are normalized. `sigma` is the standard deviation of the
+/// observed unit vector's components, roughly its angular error in radians.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VectorObservation<T> {
+    /// Unit direction in the reference frame
+    pub reference: [T; 3],
+    /// Unit direction measured in the body frame
+    pub observed: [T; 3],
+    /// Measurement standard deviation
+    pub sigma: T,
+}
+
+impl<T> VectorObservation<T>
+    where T: Float
+{
+    /// Observation of any direction, both arguments being normalized
+    pub fn new(reference: [T; 3], observed: [T; 3], sigma: T) -> VectorObservation<T> {
+        VectorObservation {
+            reference: vec3_normalized(reference),
+            observed: vec3_normalized(observed),
+            sigma,
+        }
+    }
+
+    /// Accelerometer reading of gravity while unaccelerated, with z up
+    pub fn gravity(accel: [T; 3], sigma: T) -> VectorObservation<T> {
+        VectorObservation::new([T::zero(), T::zero(), T::one()], accel, sigma)
+    }
+
+    /// Magnetometer reading of a known local field
+    pub fn magnetic(field: [T; 3], mag: [T; 3], sigma: T) -> VectorObservation<T> {
+        VectorObservation::new(field, mag, sigma)
+    }
+
+    /// Star tracker line of sight to a star with known catalog direction
+    pub fn star(catalog: [T; 3], line_of_sight: [T; 3], sigma: T) -> VectorObservation<T> {
+        VectorObservation::new(catalog, line_of_sight, sigma)
+    }
+}
+
+/// Multiplicative extended Kalman filter with gyroscope bias estimation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mekf<T> {
+    /// Process noise used by `propagate`
+    pub noise: ProcessNoise<T>,
+    q: UnitQuaternion<T>,
+    bias: [T; 3],
+    p: Covariance<T>,
+}
+
+#[allow(clippy::needless_range_loop)]
+fn mul6<T>(a: &Covariance<T>, b: &Covariance<T>) -> Covariance<T>
+    where T: Float
+{
+    let mut c = [[T::zero(); 6]; 6];
+    for i in 0..6 {
+        for j in 0..6 {
+            for k in 0..6 {
+                c[i][j] += a[i][k] * b[k][j];
+            }
+        }
+    }
+    c
+}
+
+#[allow(clippy::needless_range_loop)]
+fn transpose6<T>(a: &Covariance<T>) -> Covariance<T>
+    where T: Float
+{
+    let mut t = [[T::zero(); 6]; 6];
+    for i in 0..6 {
+        for j in 0..6 {
+            t[j][i] = a[i][j];
+        }
+    }
+    t
+}
+
+impl<T> Mekf<T>
+    where T: Float
+{
+    /// Constructs a filter from an initial orientation and zero bias
+    ///
+    /// `attitude_sigma` and `bias_sigma` are the initial standard deviations of
+    /// each attitude error angle and bias component.
+    pub fn new(q: UnitQuaternion<T>, attitude_sigma: T, bias_sigma: T,
+               noise: ProcessNoise<T>) -> Mekf<T> {
+        let mut p = [[T::zero(); 6]; 6];
+        for i in 0..3 {
+            p[i][i] = attitude_sigma * attitude_sigma;
+            p[i + 3][i + 3] = bias_sigma * bias_sigma;
+        }
+        Mekf { noise, q, bias: [T::zero(); 3], p }
+    }
+
+    /// Current orientation estimate
+    pub fn orientation(&self) -> UnitQuaternion<T> {
+        self.q
+    }
+
+    /// Current gyroscope bias estimate
+    pub fn bias(&self) -> [T; 3] {
+        self.bias
+    }
+
+    /// Covariance of the error state
+    pub fn covariance(&self) -> Covariance<T> {
+        self.p
+    }
+
+    /// Propagates over `dt` with a body frame gyroscope reading
+    #[allow(clippy::needless_range_loop)]
+    pub fn propagate(&mut self, gyro: [T; 3], dt: T) {
+        let zero = T::zero();
+        let omega = vec3_sub(gyro, self.bias);
+        self.q = kinematics::integrate(self.q, omega, dt, Frame::Body);
+
+        // the attitude error rotates backwards with the body and grows with
+        // the bias error
+        let a = to_mat3(from_rotation_vector(vec3_scale(omega, -dt)));
+        let mut phi = [[zero; 6]; 6];
+        for i in 0..3 {
+            for j in 0..3 {
+                phi[i][j] = a[i][j];
+            }
+            phi[i][i + 3] = -dt;
+            phi[i + 3][i + 3] = T::one();
+        }
+
+        let mut p = mul6(&mul6(&phi, &self.p), &transpose6(&phi));
+        for i in 0..3 {
+            p[i][i] += self.noise.gyro * self.noise.gyro * dt;
+            p[i + 3][i + 3] += self.noise.bias * self.noise.bias * dt;
+        }
+        self.p = p;
+    }
+
+    /// Corrects the state with one vector observation
+    #[allow(clippy::needless_range_loop)]
+    pub fn update(&mut self, obs: VectorObservation<T>) {
+        let zero = T::zero();
+        let h = rotate_vector(self.q.inverse(), obs.reference);
+        let h_theta = hat(h);
+
+        // P H^T, a 6x3 matrix since H = [hat(h), 0]
+        let mut pht = [[zero; 3]; 6];
+        for i in 0..6 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    pht[i][j] += self.p[i][k] * h_theta[j][k];
+                }
+            }
+        }
+
+        // S = H P H^T + R
+        let mut s = [[zero; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    s[i][j] += h_theta[i][k] * pht[k][j];
+                }
+            }
+            s[i][i] += obs.sigma * obs.sigma;
+        }
+        let s_inv = mat3_inv(s);
+
+        let mut k = [[zero; 3]; 6];
+        for i in 0..6 {
+            for j in 0..3 {
+                for l in 0..3 {
+                    k[i][j] += pht[i][l] * s_inv[l][j];
+                }
+            }
+        }
+
+        let y = vec3_sub(obs.observed, h);
+        let mut dx = [zero; 6];
+        for i in 0..6 {
+            dx[i] = k[i][0] * y[0] + k[i][1] * y[1] + k[i][2] * y[2];
+        }
+
+        // Joseph form, (I - K H) P (I - K H)^T + K R K^T, keeps P symmetric
+        let mut ikh = [[zero; 6]; 6];
+        for i in 0..6 {
+            ikh[i][i] = T::one();
+            for j in 0..3 {
+                for l in 0..3 {
+                    ikh[i][j] -= k[i][l] * h_theta[l][j];
+                }
+            }
+        }
+        let mut p = mul6(&mul6(&ikh, &self.p), &transpose6(&ikh));
+        let r = obs.sigma * obs.sigma;
+        for i in 0..6 {
+            for j in 0..6 {
+                for l in 0..3 {
+                    p[i][j] += k[i][l] * r * k[j][l];
+                }
+            }
+        }
+        self.p = p;
+
+        // fold the error state back into the nominal state
+        self.q *= from_rotation_vector([dx[0], dx[1], dx[2]]);
+        self.bias = vec3_add(self.bias, [dx[3], dx[4], dx[5]]);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::{assert_same_rotation, rot};
+
+    const NOISE: ProcessNoise<f64> = ProcessNoise { gyro: 1e-3, bias: 1e-5 };
+
+    #[test]
+    fn test_estimates_bias() {
+        let truth = rot([0.3, -1.0, 0.4], 0.8);
+        let field = [0.5, 0.0, -0.8];
+        let bias = [0.01, -0.02, 0.005];
+        let inv = truth.inverse();
+        let accel = rotate_vector(inv, [0.0, 0.0, 9.81]);
+        let mag = rotate_vector(inv, field);
+
+        let mut filter = Mekf::new(rot([1.0, 0.0, 0.0], 0.1) * truth, 0.2, 0.05, NOISE);
+        for _ in 0..3000 {
+            filter.propagate(bias, 0.01);
+            filter.update(VectorObservation::gravity(accel, 0.01));
+            filter.update(VectorObservation::magnetic(field, mag, 0.01));
+        }
+
+        assert_same_rotation(filter.orientation(), truth, 1e-3);
+        let b = filter.bias();
+        for i in 0..3 {
+            assert!((b[i] - bias[i]).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_star_tracker() {
+        let omega = [0.0, 0.01, 0.02];
+        let stars = [[1.0, 0.0, 0.0], [0.0, 0.6, 0.8], [-0.5, 0.5, 0.7]];
+        let mut truth = rot([0.0, 0.0, 1.0], 2.0);
+        let mut filter = Mekf::new(rot([0.0, 1.0, 1.0], 0.05) * truth, 0.1, 1e-3, NOISE);
+        for _ in 0..200 {
+            truth = kinematics::integrate(truth, omega, 0.1, Frame::Body);
+            filter.propagate(omega, 0.1);
+            for &star in stars.iter() {
+                let los = rotate_vector(truth.inverse(), star);
+                filter.update(VectorObservation::star(star, los, 1e-4));
+            }
+        }
+        assert_same_rotation(filter.orientation(), truth, 1e-5);
+    }
+
+    #[test]
+    fn test_covariance() {
+        let mut filter = Mekf::new(UnitQuaternion::identity(), 0.1, 0.01, NOISE);
+        let before = filter.covariance();
+        filter.update(VectorObservation::gravity([0.0, 0.0, 1.0], 0.01));
+        let after = filter.covariance();
+
+        // gravity observes roll and pitch but not yaw
+        assert!(after[0][0] < before[0][0]);
+        assert!(after[1][1] < before[1][1]);
+        assert!((after[2][2] - before[2][2]).abs() < 1e-12);
+
+        // yaw uncertainty grows while turning about z
+        filter.propagate([0.0, 0.0, 0.3], 0.1);
+        let p = filter.covariance();
+        assert!(p[2][2] > after[2][2]);
+
+        filter.propagate([0.1, 0.2, 0.3], 0.1);
+        let p = filter.covariance();
+        for (i, row) in p.iter().enumerate() {
+            for (j, &x) in row.iter().enumerate() {
+                assert!((x - p[j][i]).abs() < 1e-15);
+            }
+        }
+    }
+}
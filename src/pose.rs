@@ -0,0 +1,222 @@
+//! Rigid body poses as a rotation and a translation
+
+use vecmath::traits::Float;
+use vecmath::{Matrix4, mat4_transposed, vec3_add, vec3_scale, vec3_sub};
+use std::ops::{Mul, MulAssign};
+
+use {DualQuaternion, UnitQuaternion, from_mat3, to_mat4};
+
+
+/// Rigid transform that rotates by `rotation` and then translates by `translation`
+///
+/// Poses compose with `*` like rotations: `(a * b).transform_point(p)` is
+/// `a.transform_point(b.transform_point(p))`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose<T> {
+    /// Rotation, applied first
+    pub rotation: UnitQuaternion<T>,
+    /// Translation, applied after the rotation
+    pub translation: [T; 3],
+}
+
+impl<T> Pose<T>
+    where T: Float
+{
+    /// Constructs a pose from a rotation and a translation
+    #[inline(always)]
+    pub fn new(rotation: UnitQuaternion<T>, translation: [T; 3]) -> Pose<T> {
+        Pose { rotation, translation }
+    }
+
+    /// The identity pose
+    #[inline(always)]
+    pub fn identity() -> Pose<T> {
+        Pose::new(UnitQuaternion::identity(), [T::zero(); 3])
+    }
+
+    /// Inverse pose, undoing the translation and then the rotation
+    #[inline(always)]
+    pub fn inverse(self) -> Pose<T> {
+        let rotation = self.rotation.inverse();
+        let translation = rotation.rotate_vector(self.translation);
+        Pose::new(rotation, vec3_scale(translation, -T::one()))
+    }
+
+    /// Transforms a point, applying both rotation and translation
+    #[inline(always)]
+    pub fn transform_point(self, p: [T; 3]) -> [T; 3] {
+        vec3_add(self.rotation.rotate_vector(p), self.translation)
+    }
+
+    /// Transforms a direction, applying only the rotation
+    #[inline(always)]
+    pub fn transform_vector(self, v: [T; 3]) -> [T; 3] {
+        self.rotation.rotate_vector(v)
+    }
+
+    /// Interpolates towards `other`, with `slerp` on the rotation and linear
+    /// interpolation on the translation
+    ///
+    /// `t` is clamped to `[0, 1]`. Unlike `DualQuaternion::sclerp` the origin
+    /// moves in a straight line rather than along a screw.
+    #[inline(always)]
+    pub fn interpolate(self, other: Pose<T>, t: T) -> Pose<T> {
+        let t = t.max(T::zero()).min(T::one());
+        let d = vec3_sub(other.translation, self.translation);
+        Pose::new(self.rotation.slerp(other.rotation, t),
+                  vec3_add(self.translation, vec3_scale(d, t)))
+    }
+
+    /// Row major homogeneous transform matrix, for vecmath's `row_mat4_*`
+    #[inline(always)]
+    pub fn to_mat4(self) -> Matrix4<T> {
+        let mut m = to_mat4(self.rotation);
+        for (row, &t) in m.iter_mut().zip(self.translation.iter()) {
+            row[3] = t;
+        }
+        m
+    }
+
+    /// Column major homogeneous transform matrix, for vecmath's `col_mat4_*`
+    #[inline(always)]
+    pub fn to_col_mat4(self) -> Matrix4<T> {
+        mat4_transposed(self.to_mat4())
+    }
+
+    /// Pose of a row major homogeneous transform matrix
+    ///
+    /// The bottom row is ignored, and the rotation part is converted with
+    /// `from_mat3`.
+    #[inline(always)]
+    pub fn from_mat4(m: Matrix4<T>) -> Pose<T> {
+        let r = [
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ];
+        Pose::new(from_mat3(r), [m[0][3], m[1][3], m[2][3]])
+    }
+
+    /// Pose of a column major homogeneous transform matrix
+    #[inline(always)]
+    pub fn from_col_mat4(m: Matrix4<T>) -> Pose<T> {
+        Pose::from_mat4(mat4_transposed(m))
+    }
+}
+
+impl<T> From<(UnitQuaternion<T>, [T; 3])> for Pose<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn from(pose: (UnitQuaternion<T>, [T; 3])) -> Pose<T> {
+        Pose::new(pose.0, pose.1)
+    }
+}
+
+impl<T> From<Pose<T>> for (UnitQuaternion<T>, [T; 3]) {
+    #[inline(always)]
+    fn from(pose: Pose<T>) -> (UnitQuaternion<T>, [T; 3]) {
+        (pose.rotation, pose.translation)
+    }
+}
+
+impl<T> From<Pose<T>> for DualQuaternion<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn from(pose: Pose<T>) -> DualQuaternion<T> {
+        DualQuaternion::from_rotation_translation(pose.rotation, pose.translation)
+    }
+}
+
+/// Assumes the dual quaternion is normalized
+impl<T> From<DualQuaternion<T>> for Pose<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn from(dq: DualQuaternion<T>) -> Pose<T> {
+        Pose::new(dq.rotation(), dq.translation())
+    }
+}
+
+/// Composition, applying `other` first and then `self`
+impl<T> Mul for Pose<T>
+    where T: Float
+{
+    type Output = Pose<T>;
+
+    #[inline(always)]
+    fn mul(self, other: Pose<T>) -> Pose<T> {
+        Pose::new(self.rotation * other.rotation, self.transform_point(other.translation))
+    }
+}
+
+impl<T> MulAssign for Pose<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn mul_assign(&mut self, other: Pose<T>) {
+        *self = *self * other;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vecmath::row_mat4_transform;
+    use test_util::{assert_close, assert_same_rotation};
+
+    fn pose(axis: [f64; 3], theta: f64, t: [f64; 3]) -> Pose<f64> {
+        Pose::new(UnitQuaternion::from_axis_angle(axis, theta), t)
+    }
+
+    #[test]
+    fn test_compose_and_inverse() {
+        let a = pose([0.0, 0.0, 1.0], 0.7, [1.0, 2.0, 3.0]);
+        let b = pose([1.0, -1.0, 0.0], 2.2, [0.0, -1.0, 0.5]);
+        let p = [0.3, -0.2, 4.0];
+        assert_close((a * b).transform_point(p), a.transform_point(b.transform_point(p)), 1e-10);
+        assert_close((a.inverse() * a).transform_point(p), p, 1e-10);
+        assert_close(a.inverse().transform_point(a.transform_point(p)), p, 1e-10);
+        assert_close(a.transform_vector(p), a.rotation.rotate_vector(p), 1e-10);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let a = pose([0.0, 1.0, 0.0], 0.2, [0.0, 0.0, 0.0]);
+        let b = pose([0.0, 1.0, 0.0], 1.0, [2.0, 4.0, 0.0]);
+        let mid = a.interpolate(b, 0.5);
+        assert!((mid.rotation.angle() - 0.6).abs() < 1e-10);
+        assert_close(mid.translation, [1.0, 2.0, 0.0], 1e-10);
+        assert_eq!(a.interpolate(b, 2.0).translation, b.translation);
+    }
+
+    #[test]
+    fn test_matrices() {
+        let a = pose([0.3, 1.0, -0.4], 1.4, [5.0, -1.0, 2.0]);
+        let p = [1.0, 2.0, 3.0];
+        let m = a.to_mat4();
+        let h = row_mat4_transform(m, [p[0], p[1], p[2], 1.0]);
+        assert_close([h[0], h[1], h[2]], a.transform_point(p), 1e-10);
+        assert_eq!(h[3], 1.0);
+
+        let b = Pose::from_col_mat4(a.to_col_mat4());
+        assert_same_rotation(b.rotation, a.rotation, 1e-12);
+        assert_eq!(b.translation, a.translation);
+    }
+
+    #[test]
+    fn test_dual_quaternion() {
+        let a = pose([1.0, 0.0, 1.0], -0.9, [0.5, 0.5, -3.0]);
+        let dq: DualQuaternion<f64> = a.into();
+        let p = [2.0, 0.0, 1.0];
+        assert_close(dq.transform_point(p), a.transform_point(p), 1e-10);
+
+        let b: Pose<f64> = dq.into();
+        assert_close(b.translation, a.translation, 1e-10);
+        let (q, t) = b.into();
+        assert_eq!(q, a.rotation);
+        assert_close(t, a.translation, 1e-10);
+    }
+}
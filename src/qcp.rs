@@ -0,0 +1,356 @@
+//! Quaternion characteristic polynomial (QCP) superposition
+//!
+//! Computes the minimal root mean square deviation (RMSD) between two
+//! coordinate sets, such as molecular structures with atoms in matching
+//! order, and the rotation achieving it. Both sets are centered on their
+//! (weighted) centroids first. The RMSD alone only needs the largest
+//! eigenvalue of Davenport's `K` matrix, found by Newton's method on its
+//! characteristic polynomial, which is much cheaper than a full
+//! eigen-decomposition. See Theobald, "Rapid calculation of RMSDs using a
+//! quaternion-based characteristic polynomial" (2005) and Liu, Agrafiotis and
+//! Theobald, "Fast determination of the optimal rotational matrix for
+//! macromolecular superpositions" (2010).
+
+use vecmath::traits::Float;
+use vecmath::{Matrix3, Matrix4, mat3_det, mat4_det, vec3_add, vec3_dot, vec3_scale, vec3_sub};
+
+use {Quaternion, UnitQuaternion, normalize, rotate_vector, try_normalize};
+use eigen::{davenport_k, dominant_eigenvector};
+
+
+/// Optimal superposition of one coordinate set onto another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Superposition<T> {
+    /// Root mean square deviation after superposition
+    pub rmsd: T,
+    /// Rotation of the centered `from` set onto the centered `to` set
+    pub rotation: UnitQuaternion<T>,
+    /// Translation applied after the rotation
+    pub translation: [T; 3],
+}
+
+impl<T> Superposition<T>
+    where T: Float
+{
+    /// Maps a point of the `from` set onto the `to` set
+    pub fn transform(&self, p: [T; 3]) -> [T; 3] {
+        vec3_add(rotate_vector(self.rotation, p), self.translation)
+    }
+}
+
+/// Quantities of the centered coordinate sets needed by QCP
+struct Centered<T> {
+    /// Inner product matrix `sum(w[i] * to[i] * from[i]^T)`
+    b: Matrix3<T>,
+    /// `sum(w[i] * (|from[i]|^2 + |to[i]|^2))`
+    g: T,
+    /// Sum of the weights
+    total: T,
+    from_mean: [T; 3],
+    to_mean: [T; 3],
+}
+
+#[allow(clippy::needless_range_loop)]
+fn center<T>(from: &[[T; 3]], to: &[[T; 3]], weights: &[T]) -> Option<Centered<T>>
+    where T: Float
+{
+    assert!(from.len() == to.len() && to.len() == weights.len(),
+            "from, to and weights must have the same length");
+
+    let zero = T::zero();
+    let mut total = zero;
+    let mut from_mean = [zero; 3];
+    let mut to_mean = [zero; 3];
+    for n in 0..from.len() {
+        total += weights[n];
+        from_mean = vec3_add(from_mean, vec3_scale(from[n], weights[n]));
+        to_mean = vec3_add(to_mean, vec3_scale(to[n], weights[n]));
+    }
+    if from.is_empty() || total <= zero {
+        return None;
+    }
+    from_mean = vec3_scale(from_mean, T::one() / total);
+    to_mean = vec3_scale(to_mean, T::one() / total);
+
+    let mut b = [[zero; 3]; 3];
+    let mut g = zero;
+    for n in 0..from.len() {
+        let w = weights[n];
+        let a = vec3_sub(from[n], from_mean);
+        let c = vec3_sub(to[n], to_mean);
+        g += w * (vec3_dot(a, a) + vec3_dot(c, c));
+        for i in 0..3 {
+            for j in 0..3 {
+                b[i][j] += w * c[i] * a[j];
+            }
+        }
+    }
+    Some(Centered { b, g, total, from_mean, to_mean })
+}
+
+/// Largest eigenvalue of `K`, by Newton's method from the upper bound `g / 2`
+fn max_eigenvalue<T>(k: Matrix4<T>, centered: &Centered<T>) -> T
+    where T: Float
+{
+    let two = T::one() + T::one();
+    let b = centered.b;
+
+    // K is traceless, so its characteristic polynomial is
+    // x^4 + c2 x^2 + c1 x + c0
+    let frobenius = b.iter().fold(T::zero(), |acc, row| acc + vec3_dot(*row, *row));
+    let c2 = -two * frobenius;
+    let c1 = -T::from_f64(8.0) * mat3_det(b);
+    let c0 = mat4_det(k);
+
+    let mut lambda = centered.g / two;
+    for _ in 0..50 {
+        let l2 = lambda * lambda;
+        let p = (l2 + c2) * l2 + c1 * lambda + c0;
+        let dp = T::from_f64(4.0) * l2 * lambda + two * c2 * lambda + c1;
+        if dp == T::zero() {
+            break;
+        }
+        let step = p / dp;
+        lambda -= step;
+        let abs_step = if step < T::zero() { -step } else { step };
+        let abs_lambda = if lambda < T::zero() { -lambda } else { lambda };
+        if abs_step <= T::from_f64(1e-11) * abs_lambda {
+            break;
+        }
+    }
+    lambda
+}
+
+/// RMSD given the centered sets and the largest eigenvalue of `K`
+fn rmsd_from_eigenvalue<T>(centered: &Centered<T>, lambda: T) -> T
+    where T: Float
+{
+    let two = T::one() + T::one();
+    let msd = (centered.g - two * lambda) / centered.total;
+    if msd > T::zero() { msd.sqrt() } else { T::zero() }
+}
+
+/// Unnormalized eigenvector of `K` for eigenvalue `lambda`, the largest
+/// column of the adjugate of `(K - lambda I) / scale`
+///
+/// The adjugate is cubic in its matrix, so `scale` should be of the size of
+/// the entries of `K` to keep it from overflowing.
+#[allow(clippy::needless_range_loop)]
+fn eigenvector<T>(k: Matrix4<T>, lambda: T, scale: T) -> Quaternion<T>
+    where T: Float
+{
+    let mut a = k;
+    for i in 0..4 {
+        a[i][i] -= lambda;
+        for j in 0..4 {
+            a[i][j] /= scale;
+        }
+    }
+
+    let minor = |row: usize, col: usize| {
+        let mut m = [[T::zero(); 3]; 3];
+        let rows = (0..4).filter(|&i| i != row);
+        for (r, i) in rows.enumerate() {
+            let cols = (0..4).filter(|&j| j != col);
+            for (c, j) in cols.enumerate() {
+                m[r][c] = a[i][j];
+            }
+        }
+        mat3_det(m)
+    };
+
+    // a is symmetric, so its adjugate is too and columns are cofactor rows
+    let mut best = Quaternion::new(T::zero(), T::zero(), T::zero(), T::zero());
+    for row in 0..4 {
+        let mut v = [T::zero(); 4];
+        for col in 0..4 {
+            let m = minor(row, col);
+            v[col] = if (row + col) % 2 == 0 { m } else { -m };
+        }
+        let q = Quaternion::new(v[0], v[1], v[2], v[3]);
+        if ::square_len(q) > ::square_len(best) {
+            best = q;
+        }
+    }
+    best
+}
+
+/// Weighted minimal RMSD between two coordinate sets
+///
+/// For collinear sets the eigenvalue is repeated and Newton's method only
+/// reaches about the square root of the machine precision, while
+/// `weighted_superpose` stays accurate. Returns `None` if the sets are empty
+/// or the weights sum to zero. Panics if the slices differ in length.
+pub fn weighted_rmsd<T>(from: &[[T; 3]], to: &[[T; 3]], weights: &[T]) -> Option<T>
+    where T: Float
+{
+    let centered = center(from, to, weights)?;
+    let lambda = max_eigenvalue(davenport_k(centered.b), &centered);
+    Some(rmsd_from_eigenvalue(&centered, lambda))
+}
+
+/// Unweighted `weighted_rmsd`
+pub fn rmsd<T>(from: &[[T; 3]], to: &[[T; 3]]) -> Option<T>
+    where T: Float
+{
+    weighted_rmsd(from, to, &vec![T::one(); from.len()])
+}
+
+/// Weighted optimal superposition of `from` onto `to`
+///
+/// When the rotation is not unique, such as for collinear points, one of the
+/// optimal rotations is returned. Returns `None` if the sets are empty or the
+/// weights sum to zero. Panics if the slices differ in length.
+pub fn weighted_superpose<T>(from: &[[T; 3]], to: &[[T; 3]], weights: &[T])
+    -> Option<Superposition<T>>
+    where T: Float
+{
+    let centered = center(from, to, weights)?;
+    let k = davenport_k(centered.b);
+    let lambda = max_eigenvalue(k, &centered);
+
+    // the adjugate vanishes when lambda is a repeated eigenvalue, where
+    // Newton's method also converges slowly, so fall back to a full
+    // eigen-decomposition then, or if the adjugate is not finite, which
+    // leaves a normalized vector that is not of unit length
+    let v = eigenvector(k, lambda, centered.g);
+    let (lambda, q) = match try_normalize(v, T::from_f64(1e-6)) {
+        Some(q) if ::square_len(q) > T::from_f64(0.5) => (lambda, q),
+        _ => {
+            let (lambda, q) = dominant_eigenvector(k);
+            (lambda, normalize(q))
+        }
+    };
+    let rotation = UnitQuaternion::new_unchecked(q);
+
+    let moved = rotate_vector(rotation, centered.from_mean);
+    Some(Superposition {
+        rmsd: rmsd_from_eigenvalue(&centered, lambda),
+        rotation,
+        translation: vec3_sub(centered.to_mean, moved),
+    })
+}
+
+/// Unweighted `weighted_superpose`
+pub fn superpose<T>(from: &[[T; 3]], to: &[[T; 3]]) -> Option<Superposition<T>>
+    where T: Float
+{
+    weighted_superpose(from, to, &vec![T::one(); from.len()])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use registration::absolute_orientation;
+    use test_util::assert_same_rotation;
+
+    // the 7 atom fragments from Theobald's reference implementation, qcprot
+    static FRAG_A: [[f64; 3]; 7] = [
+        [-2.803, -15.373, 24.556],
+        [0.893, -16.062, 25.147],
+        [1.368, -12.371, 25.885],
+        [-1.651, -12.153, 28.177],
+        [-0.440, -15.218, 30.068],
+        [2.551, -13.273, 31.372],
+        [0.105, -11.330, 33.567],
+    ];
+    static FRAG_B: [[f64; 3]; 7] = [
+        [-14.739, -18.673, 15.040],
+        [-12.473, -15.810, 16.074],
+        [-14.802, -13.307, 14.408],
+        [-17.782, -14.852, 16.171],
+        [-16.124, -14.617, 19.584],
+        [-15.029, -11.037, 18.902],
+        [-18.577, -10.001, 17.996],
+    ];
+
+    fn direct_rmsd(s: &Superposition<f64>, from: &[[f64; 3]], to: &[[f64; 3]]) -> f64 {
+        let sum: f64 = from.iter().zip(to.iter()).map(|(&a, &b)| {
+            let d = vec3_sub(s.transform(a), b);
+            vec3_dot(d, d)
+        }).sum();
+        (sum / from.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_reference_fragments() {
+        let r = rmsd(&FRAG_B, &FRAG_A).unwrap();
+        assert!((r - 0.719106).abs() < 1e-6);
+
+        let s = superpose(&FRAG_B, &FRAG_A).unwrap();
+        assert!((s.rmsd - r).abs() < 1e-12);
+        assert!((direct_rmsd(&s, &FRAG_B, &FRAG_A) - r).abs() < 1e-9);
+
+        // agrees with Horn's method, which uses a full eigen-decomposition
+        let horn = absolute_orientation(&FRAG_B, &FRAG_A, false).unwrap();
+        assert_same_rotation(horn.rotation, s.rotation, 1e-12);
+    }
+
+    #[test]
+    fn test_weights() {
+        // a zero weight removes an atom entirely
+        let mut weights = [1.0; 7];
+        weights[6] = 0.0;
+        let w = weighted_superpose(&FRAG_B, &FRAG_A, &weights).unwrap();
+        let s = superpose(&FRAG_B[..6], &FRAG_A[..6]).unwrap();
+        assert!((w.rmsd - s.rmsd).abs() < 1e-10);
+        assert_same_rotation(w.rotation, s.rotation, 1e-12);
+
+        // uniform weights scale out
+        let r = weighted_rmsd(&FRAG_B, &FRAG_A, &[3.0; 7]).unwrap();
+        assert!((r - rmsd(&FRAG_B, &FRAG_A).unwrap()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_identical_structures() {
+        let q = UnitQuaternion::from_axis_angle([0.3, 0.4, -1.0], 2.8);
+        let moved: Vec<[f64; 3]> = FRAG_A.iter()
+            .map(|&p| vec3_add(rotate_vector(q, p), [1.0, 2.0, 3.0]))
+            .collect();
+        let s = superpose(&FRAG_A, &moved).unwrap();
+        assert!(s.rmsd < 1e-6);
+        assert_same_rotation(q, s.rotation, 1e-12);
+        assert!(direct_rmsd(&s, &FRAG_A, &moved) < 1e-9);
+    }
+
+    #[test]
+    fn test_large_f32() {
+        // a few thousand atoms around +-40, where the adjugate of K overflows
+        // f32 unless it is scaled
+        let from: Vec<[f32; 3]> = (0..3000).map(|n| {
+            let n = n as f32;
+            [40.0 * (1.3 * n).sin(), 40.0 * (0.7 * n + 1.0).cos(), 40.0 * (0.37 * n + 2.0).sin()]
+        }).collect();
+        let q = UnitQuaternion::from_axis_angle([0.3, 0.4, -1.0], 2.8);
+        let to: Vec<[f32; 3]> = from.iter()
+            .map(|&p| vec3_add(rotate_vector(q, p), [1.0, 2.0, 3.0]))
+            .collect();
+
+        let s = superpose(&from, &to).unwrap();
+        assert!((::len(*s.rotation) - 1.0).abs() < 1e-6);
+        assert!((q.inverse() * s.rotation).angle() < 1e-5);
+        assert!(s.rmsd < 1e-2);
+        for (&a, &b) in from.iter().zip(to.iter()) {
+            let d = vec3_sub(s.transform(a), b);
+            assert!(vec3_dot(d, d) < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_degenerate() {
+        let empty: [[f64; 3]; 0] = [];
+        assert_eq!(rmsd(&empty, &empty), None);
+        assert_eq!(weighted_rmsd(&FRAG_A, &FRAG_B, &[0.0; 7]), None);
+        let s = superpose(&FRAG_A[..1], &FRAG_B[..1]).unwrap();
+        assert_eq!(s.rmsd, 0.0);
+
+        // collinear sets leave the twist about the line free
+        let from = [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [2.0, 2.0, 0.0]];
+        let d = 2.0f64.sqrt();
+        let to = [[5.0, 0.0, 0.0], [5.0, 0.0, d], [5.0, 0.0, 2.0 * d]];
+        let s = superpose(&from, &to).unwrap();
+        assert!(s.rmsd < 1e-6);
+        assert!(direct_rmsd(&s, &from, &to) < 1e-6);
+    }
+}
@@ -0,0 +1,159 @@
+//! Absolute orientation of corresponding point sets
+//!
+//! Finds the similarity transform `to[i] ~ scale * rotate_vector(rotation,
+//! from[i]) + translation` minimizing the summed squared error, with Horn's
+//! closed-form quaternion method. See Horn, "Closed-form solution of absolute
+//! orientation using unit quaternions" (1987).
+
+use vecmath::traits::Float;
+use vecmath::{vec3_add, vec3_dot, vec3_scale, vec3_sub};
+
+use {UnitQuaternion, normalize, rotate_vector};
+use eigen::{davenport_k, dominant_eigenvector};
+
+
+/// Similarity transform aligning one point set with another
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AbsoluteOrientation<T> {
+    /// Rotation applied first, after scaling
+    pub rotation: UnitQuaternion<T>,
+    /// Translation applied last
+    pub translation: [T; 3],
+    /// Uniform scale, one unless requested
+    pub scale: T,
+}
+
+impl<T> AbsoluteOrientation<T>
+    where T: Float
+{
+    /// Maps a point of the `from` set onto the `to` set
+    pub fn transform(&self, p: [T; 3]) -> [T; 3] {
+        vec3_add(vec3_scale(rotate_vector(self.rotation, p), self.scale), self.translation)
+    }
+}
+
+/// Mean of a point set
+fn centroid<T>(points: &[[T; 3]]) -> [T; 3]
+    where T: Float
+{
+    let sum = points.iter().fold([T::zero(); 3], |acc, &p| vec3_add(acc, p));
+    vec3_scale(sum, T::one() / T::from_f64(points.len() as f64))
+}
+
+/// Horn's absolute orientation between corresponding point sets
+///
+/// With `with_scale` the scale is estimated too, using Horn's symmetric
+/// formula, the ratio of the point sets' spreads about their centroids.
+/// Returns `None` for empty point sets, or when estimating scale and the
+/// `from` points all coincide. Panics if the slices differ in length.
+#[allow(clippy::needless_range_loop)]
+pub fn absolute_orientation<T>(from: &[[T; 3]], to: &[[T; 3]], with_scale: bool)
+    -> Option<AbsoluteOrientation<T>>
+    where T: Float
+{
+    assert!(from.len() == to.len(), "point sets must have the same length");
+    if from.is_empty() {
+        return None;
+    }
+
+    let from_mean = centroid(from);
+    let to_mean = centroid(to);
+
+    // cross covariance of the centered sets
+    let mut m = [[T::zero(); 3]; 3];
+    let mut from_spread = T::zero();
+    let mut to_spread = T::zero();
+    for n in 0..from.len() {
+        let a = vec3_sub(from[n], from_mean);
+        let b = vec3_sub(to[n], to_mean);
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] += b[i] * a[j];
+            }
+        }
+        from_spread += vec3_dot(a, a);
+        to_spread += vec3_dot(b, b);
+    }
+
+    let (_, q) = dominant_eigenvector(davenport_k(m));
+    let rotation = UnitQuaternion::new_unchecked(normalize(q));
+
+    let scale = if with_scale {
+        if from_spread <= T::zero() {
+            return None;
+        }
+        (to_spread / from_spread).sqrt()
+    } else {
+        T::one()
+    };
+
+    let moved = vec3_scale(rotate_vector(rotation, from_mean), scale);
+    Some(AbsoluteOrientation {
+        rotation,
+        translation: vec3_sub(to_mean, moved),
+        scale,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::assert_same_rotation;
+
+    static POINTS: [[f64; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.0, 2.0, 0.0],
+        [0.0, 0.0, 3.0],
+        [1.0, -1.0, 0.5],
+    ];
+
+    fn transformed(t: &AbsoluteOrientation<f64>) -> Vec<[f64; 3]> {
+        POINTS.iter().map(|&p| t.transform(p)).collect()
+    }
+
+    #[test]
+    fn test_recovers_rigid_transform() {
+        let truth = AbsoluteOrientation {
+            rotation: UnitQuaternion::from_axis_angle([1.0, -2.0, 0.5], 2.5),
+            translation: [3.0, -1.0, 0.25],
+            scale: 1.0,
+        };
+        let to = transformed(&truth);
+        let fit = absolute_orientation(&POINTS, &to, false).unwrap();
+
+        assert_same_rotation(fit.rotation, truth.rotation, 1e-12);
+        assert_eq!(fit.scale, 1.0);
+        for (&p, &q) in POINTS.iter().zip(to.iter()) {
+            let r = fit.transform(p);
+            for i in 0..3 {
+                assert!((r[i] - q[i]).abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn test_recovers_scale() {
+        let truth = AbsoluteOrientation {
+            rotation: UnitQuaternion::from_axis_angle([0.0, 1.0, 1.0], -0.6),
+            translation: [0.5, 0.5, -2.0],
+            scale: 2.5,
+        };
+        let to = transformed(&truth);
+        let fit = absolute_orientation(&POINTS, &to, true).unwrap();
+
+        assert!((fit.scale - 2.5).abs() < 1e-10);
+        for i in 0..3 {
+            assert!((fit.translation[i] - truth.translation[i]).abs() < 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_degenerate() {
+        let empty: [[f64; 3]; 0] = [];
+        assert_eq!(absolute_orientation(&empty, &empty, false), None);
+        let same = [[1.0, 2.0, 3.0]; 3];
+        assert_eq!(absolute_orientation(&same, &POINTS[..3], true), None);
+    }
+}
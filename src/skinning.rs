@@ -0,0 +1,171 @@
+//! Dual quaternion skinning of mesh vertex buffers
+//!
+//! Each vertex is deformed by a blend of the transforms of the joints that
+//! influence it. Blending dual quaternions instead of matrices keeps the
+//! result rigid, which avoids the volume loss ("candy wrapper" artifact) that
+//! linear blend skinning shows around twisting joints. See Kavan et al.,
+//! "Skinning with Dual Quaternions" (2007).
+//!
+//! Buffers are flat slices as found in mesh data: positions and normals hold
+//! three components per vertex, and joint indices and weights hold a fixed
+//! number of influences per vertex.
+
+use vecmath::traits::Float;
+
+use DualQuaternion;
+use dual;
+
+
+/// Joint influences of every vertex, a fixed number per vertex
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Influences<'a, I: 'a, T: 'a> {
+    indices: &'a [I],
+    weights: &'a [T],
+    per_vertex: usize,
+}
+
+impl<'a, I, T> Influences<'a, I, T>
+    where I: Copy + Into<u32>, T: Float
+{
+    /// Wraps joint index and weight buffers with `per_vertex` entries per vertex
+    ///
+    /// Panics if the buffers differ in length, or if `per_vertex` is zero or
+    /// does not divide their length.
+    pub fn new(indices: &'a [I], weights: &'a [T], per_vertex: usize) -> Influences<'a, I, T> {
+        assert_eq!(indices.len(), weights.len(), "one weight is needed per joint index");
+        assert!(per_vertex > 0 && indices.len().is_multiple_of(per_vertex),
+                "buffers must hold a whole number of vertices");
+        Influences { indices, weights, per_vertex }
+    }
+
+    /// Number of vertices described
+    pub fn vertices(&self) -> usize {
+        self.indices.len() / self.per_vertex
+    }
+
+    /// Blended transform of vertex `v`, or `None` if its weights sum to zero
+    ///
+    /// Influences with zero weight are ignored, so padding slots may hold any
+    /// joint index.
+    pub fn blend(&self, joints: &[DualQuaternion<T>], v: usize) -> Option<DualQuaternion<T>> {
+        let zero = T::zero();
+        let start = v * self.per_vertex;
+        let end = start + self.per_vertex;
+        let influences = self.indices[start..end].iter().zip(self.weights[start..end].iter())
+            .filter(|&(_, &w)| w != zero)
+            .map(|(&i, &w)| (joints[i.into() as usize], w));
+        dual::blend(influences)
+    }
+}
+
+/// Skins vertex positions and normals by the joint transforms
+///
+/// `joints` holds each joint's transform from bind pose to current pose.
+/// Vertices whose weights sum to zero are copied unchanged. Normals are
+/// skipped when `normals` is empty. Panics if an output buffer differs in
+/// length from its input, if the buffers do not describe the same number of
+/// vertices, or if a joint index with nonzero weight is out of range.
+pub fn skin<I, T>(joints: &[DualQuaternion<T>], influences: &Influences<I, T>,
+                  positions: &[T], normals: &[T],
+                  out_positions: &mut [T], out_normals: &mut [T])
+    where I: Copy + Into<u32>, T: Float
+{
+    let n = influences.vertices();
+    assert_eq!(positions.len(), 3 * n, "positions must hold 3 components per vertex");
+    assert_eq!(out_positions.len(), positions.len(), "output positions must match the input");
+    assert!(normals.is_empty() || normals.len() == positions.len(),
+            "normals must be empty or match positions");
+    assert_eq!(out_normals.len(), normals.len(), "output normals must match the input");
+
+    for v in 0..n {
+        let k = 3 * v;
+        let p = [positions[k], positions[k + 1], positions[k + 2]];
+        let dq = influences.blend(joints, v);
+        let p = dq.map_or(p, |dq| dq.transform_point(p));
+        out_positions[k..k + 3].copy_from_slice(&p);
+
+        if !normals.is_empty() {
+            let m = [normals[k], normals[k + 1], normals[k + 2]];
+            let m = dq.map_or(m, |dq| dq.transform_vector(m));
+            out_normals[k..k + 3].copy_from_slice(&m);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use UnitQuaternion;
+
+    #[test]
+    fn test_rigid_vertices() {
+        let q = UnitQuaternion::from_axis_angle([0.0, 1.0, 0.0], 0.5);
+        let joints = [
+            DualQuaternion::identity(),
+            DualQuaternion::from_rotation_translation(q, [0.0, 0.0, 1.0]),
+        ];
+        let indices: [u16; 4] = [0, 1, 1, 0];
+        let weights = [1.0f64, 0.0, 1.0, 0.0];
+        let influences = Influences::new(&indices, &weights, 2);
+
+        let positions = [1.0, 2.0, 3.0, 1.0, 0.0, 0.0];
+        let normals = [0.0, 0.0, 1.0, 0.0, 1.0, 0.0];
+        let mut out_p = [0.0; 6];
+        let mut out_n = [0.0; 6];
+        skin(&joints, &influences, &positions, &normals, &mut out_p, &mut out_n);
+
+        assert_eq!(&out_p[..3], &positions[..3]);
+        let p = joints[1].transform_point([1.0, 0.0, 0.0]);
+        let m = joints[1].transform_vector([0.0, 1.0, 0.0]);
+        for i in 0..3 {
+            assert!((out_p[3 + i] - p[i]).abs() < 1e-12);
+            assert!((out_n[3 + i] - m[i]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_twist_keeps_volume() {
+        // halfway between an untwisted and a nearly half twisted joint, linear
+        // blend skinning would pull the vertex almost onto the axis
+        let twist = UnitQuaternion::from_axis_angle([1.0, 0.0, 0.0], 3.0);
+        let joints = [DualQuaternion::identity(), DualQuaternion::from_rotation(twist)];
+        let indices: [u8; 2] = [0, 1];
+        let weights = [0.5f64, 0.5];
+        let influences = Influences::new(&indices, &weights, 2);
+
+        let mut out = [0.0; 3];
+        skin(&joints, &influences, &[2.0, 1.0, 0.0], &[], &mut out, &mut []);
+        assert!((out[0] - 2.0).abs() < 1e-12);
+        assert!((out[1] * out[1] + out[2] * out[2] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_zero_weight_padding_first() {
+        let joints = [
+            DualQuaternion::identity(),
+            DualQuaternion::from_rotation(UnitQuaternion::from_axis_angle([1.0, 0.0, 0.0], 3.0)),
+            DualQuaternion::from_rotation(UnitQuaternion::from_axis_angle([1.0, 0.0, 0.0], 3.3)),
+        ];
+        // the padding slot comes first and points past the last joint
+        let indices: [u8; 6] = [0, 1, 2, 255, 1, 2];
+        let weights = [0.0f64, 0.5, 0.5, 0.0, 0.5, 0.5];
+        let influences = Influences::new(&indices, &weights, 3);
+
+        let mut out = [0.0; 6];
+        skin(&joints, &influences, &[0.0, 1.0, 0.0, 0.0, 1.0, 0.0], &[], &mut out, &mut []);
+        let expected = [0.0, 3.15f64.cos(), 3.15f64.sin()];
+        for i in 0..6 {
+            assert!((out[i] - expected[i % 3]).abs() < 1e-12, "{:?}", out);
+        }
+    }
+
+    #[test]
+    fn test_zero_weights() {
+        let joints = [DualQuaternion::from_translation([1.0, 1.0, 1.0])];
+        let influences = Influences::new(&[0u32], &[0.0], 1);
+        let mut out = [0.0; 3];
+        skin(&joints, &influences, &[4.0, 5.0, 6.0], &[], &mut out, &mut []);
+        assert_eq!(out, [4.0, 5.0, 6.0]);
+    }
+}
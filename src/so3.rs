@@ -0,0 +1,129 @@
+//! Lie group operations on SO(3), the rotation group, with unit quaternions
+//!
+//! The tangent space is represented by rotation vectors, whose direction is
+//! the rotation axis and whose norm is the angle. `boxplus` perturbs on the
+//! right, in the body frame, as `mekf` does with its attitude error:
+//! `boxplus(q, delta) = q * Exp(delta)` and `boxminus(a, b) = Log(b^-1 * a)`,
+//! so that `boxplus(b, boxminus(a, b))` is `a`. Left (world frame)
+//! perturbations convert with the adjoint: `Exp(adjoint(q) * delta) * q`
+//! equals `q * Exp(delta)`.
+
+use vecmath::traits::Float;
+use vecmath::Matrix3;
+
+use {UnitQuaternion, from_rotation_vector, to_mat3, to_rotation_vector};
+
+
+/// Skew symmetric matrix of `v`, such that `hat(v) * u` is `v x u`
+#[inline(always)]
+pub fn hat<T>(v: [T; 3]) -> Matrix3<T>
+    where T: Float
+{
+    let zero = T::zero();
+    [
+        [zero, -v[2], v[1]],
+        [v[2], zero, -v[0]],
+        [-v[1], v[0], zero],
+    ]
+}
+
+/// Vector of a skew symmetric matrix, the inverse of `hat`
+///
+/// Only the skew symmetric part of `m` is used.
+#[inline(always)]
+pub fn vee<T>(m: Matrix3<T>) -> [T; 3]
+    where T: Float
+{
+    let half = T::from_f64(0.5);
+    [
+        (m[2][1] - m[1][2]) * half,
+        (m[0][2] - m[2][0]) * half,
+        (m[1][0] - m[0][1]) * half,
+    ]
+}
+
+/// Exponential map from a rotation vector to a rotation
+#[inline(always)]
+pub fn exp<T>(v: [T; 3]) -> UnitQuaternion<T>
+    where T: Float
+{
+    from_rotation_vector(v)
+}
+
+/// Logarithm map from a rotation to its rotation vector, with angle in `[0, pi]`
+#[inline(always)]
+pub fn log<T>(q: UnitQuaternion<T>) -> [T; 3]
+    where T: Float
+{
+    to_rotation_vector(q)
+}
+
+/// Perturbs `q` by `delta` in its body frame, `q * Exp(delta)`
+#[inline(always)]
+pub fn boxplus<T>(q: UnitQuaternion<T>, delta: [T; 3]) -> UnitQuaternion<T>
+    where T: Float
+{
+    q * exp(delta)
+}
+
+/// Body frame difference `Log(b^-1 * a)`, the inverse of `boxplus`
+///
+/// The shorter of the two rotations between the orientations is taken.
+#[inline(always)]
+pub fn boxminus<T>(a: UnitQuaternion<T>, b: UnitQuaternion<T>) -> [T; 3]
+    where T: Float
+{
+    log(b.inverse() * a)
+}
+
+/// Adjoint of `q`, mapping body frame tangent vectors to world frame ones
+///
+/// For SO(3) this is the rotation matrix of `q`.
+#[inline(always)]
+pub fn adjoint<T>(q: UnitQuaternion<T>) -> Matrix3<T>
+    where T: Float
+{
+    to_mat3(q)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vecmath::{row_mat3_transform, vec3_cross};
+    use test_util::{assert_close, assert_same_rotation};
+
+    #[test]
+    fn test_hat_vee() {
+        let v = [1.0, -2.0, 0.5];
+        let u = [0.3, 0.7, -1.1];
+        assert_close(row_mat3_transform(hat(v), u), vec3_cross(v, u), 1e-10);
+        assert_eq!(vee(hat(v)), v);
+    }
+
+    #[test]
+    fn test_exp_log() {
+        let v = [0.4, -1.2, 2.0];
+        assert_close(log(exp(v)), v, 1e-10);
+        assert_eq!(log(UnitQuaternion::<f64>::identity()), [0.0; 3]);
+
+        // angles past pi wrap to the shorter rotation the other way
+        let long = [0.0, 0.0, 4.0];
+        let pi2 = 2.0 * ::std::f64::consts::PI;
+        assert_close(log(exp(long)), [0.0, 0.0, 4.0 - pi2], 1e-10);
+    }
+
+    #[test]
+    fn test_boxplus_boxminus() {
+        let a = UnitQuaternion::from_axis_angle([1.0, 2.0, 3.0], 0.8);
+        let b = UnitQuaternion::from_axis_angle([-1.0, 0.0, 1.0], 2.5);
+        assert_same_rotation(boxplus(b, boxminus(a, b)), a, 1e-12);
+
+        let delta = [0.1, -0.2, 0.3];
+        assert_close(boxminus(boxplus(a, delta), a), delta, 1e-10);
+
+        // a body frame perturbation is a world frame one through the adjoint
+        let world = row_mat3_transform(adjoint(a), delta);
+        assert_same_rotation(exp(world) * a, boxplus(a, delta), 1e-12);
+    }
+}
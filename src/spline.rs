@@ -0,0 +1,339 @@
+//! Smooth orientation curves through sequences of unit quaternion keyframes
+//!
+//! Every curve here passes through its keyframes. Keyframes are first flipped
+//! into a common hemisphere so each segment takes the short way around.
+
+use vecmath::traits::Float;
+use vecmath::{vec3_add, vec3_scale};
+
+use {Quaternion, UnitQuaternion, conj, dot, mul, normalize, slerp_unclamped};
+
+
+/// Logarithm of the rotation taking `from` to `to`, expressed in the frame of `from`
+#[inline(always)]
+fn log_between<T>(from: Quaternion<T>, to: Quaternion<T>) -> [T; 3]
+    where T: Float
+{
+    UnitQuaternion::new_unchecked(mul(conj(from), to)).ln()
+}
+
+/// Copies the keyframes, negating any that lie in the opposite hemisphere to
+/// their predecessor
+fn align_hemispheres<T>(keys: &[Quaternion<T>]) -> Vec<Quaternion<T>>
+    where T: Float
+{
+    let mut aligned: Vec<Quaternion<T>> = Vec::with_capacity(keys.len());
+    for &k in keys {
+        let k = match aligned.last() {
+            Some(&prev) if dot(prev, k) < T::zero() => -k,
+            _ => k,
+        };
+        aligned.push(k);
+    }
+    aligned
+}
+
+/// Spherical quadrangle interpolation between `q0` and `q1`
+///
+/// `s0` and `s1` are the inner control points, as given by `squad_control_point`.
+#[inline(always)]
+pub fn squad<T>(q0: Quaternion<T>, q1: Quaternion<T>,
+                s0: Quaternion<T>, s1: Quaternion<T>, t: T) -> Quaternion<T>
+    where T: Float
+{
+    let two = T::one() + T::one();
+    let outer = slerp_unclamped(q0, q1, t);
+    let inner = slerp_unclamped(s0, s1, t);
+    slerp_unclamped(outer, inner, two * t * (T::one() - t))
+}
+
+/// Inner SQUAD control point at `cur` that makes the curve C1 through it
+#[inline(always)]
+pub fn squad_control_point<T>(prev: Quaternion<T>, cur: Quaternion<T>,
+                              next: Quaternion<T>) -> Quaternion<T>
+    where T: Float
+{
+    let quarter = T::from_f64(0.25);
+    let sum = vec3_add(log_between(cur, prev), log_between(cur, next));
+    mul(cur, *UnitQuaternion::exp(vec3_scale(sum, -quarter)))
+}
+
+/// De Casteljau evaluation of a Bézier curve on the unit sphere in four dimensions
+///
+/// Each round of linear interpolation is replaced by slerp. Panics if `points`
+/// is empty.
+pub fn bezier<T>(points: &[Quaternion<T>], t: T) -> Quaternion<T>
+    where T: Float
+{
+    assert!(!points.is_empty(), "bezier needs at least one control point");
+
+    let mut work = points.to_vec();
+    for n in (1..work.len()).rev() {
+        for i in 0..n {
+            work[i] = slerp_unclamped(work[i], work[i + 1], t);
+        }
+    }
+    work[0]
+}
+
+/// Tension, continuity and bias of a Kochanek–Bartels keyframe
+///
+/// All zero gives the Catmull–Rom style curve of a plain SQUAD spline.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tcb<T> {
+    /// Tightens the curve around the keyframe as it approaches 1
+    pub tension: T,
+    /// Allows a corner at the keyframe as it moves away from 0
+    pub continuity: T,
+    /// Overshoots (positive) or undershoots (negative) the keyframe
+    pub bias: T,
+}
+
+/// SQUAD spline through a sequence of keyframes
+#[derive(Clone, Debug, PartialEq)]
+pub struct SquadSpline<T> {
+    keys: Vec<Quaternion<T>>,
+    outgoing: Vec<Quaternion<T>>,
+    incoming: Vec<Quaternion<T>>,
+}
+
+impl<T> SquadSpline<T>
+    where T: Float
+{
+    /// Builds a C1 spline, computing the inner control points automatically
+    pub fn new(keys: &[Quaternion<T>]) -> SquadSpline<T> {
+        let keys = align_hemispheres(keys);
+        let n = keys.len();
+        let controls: Vec<Quaternion<T>> = (0..n).map(|i| {
+            let prev = keys[if i == 0 { 0 } else { i - 1 }];
+            let next = keys[if i + 1 == n { i } else { i + 1 }];
+            squad_control_point(prev, keys[i], next)
+        }).collect();
+
+        SquadSpline { keys, outgoing: controls.clone(), incoming: controls }
+    }
+
+    /// Builds a Kochanek–Bartels spline with one set of parameters per keyframe
+    ///
+    /// Panics if `params` and `keys` differ in length.
+    pub fn kochanek_bartels(keys: &[Quaternion<T>], params: &[Tcb<T>]) -> SquadSpline<T> {
+        assert_eq!(keys.len(), params.len(), "one Tcb is needed per keyframe");
+
+        let keys = align_hemispheres(keys);
+        let n = keys.len();
+        let one = T::one();
+        let half = T::from_f64(0.5);
+
+        let mut outgoing = Vec::with_capacity(n);
+        let mut incoming = Vec::with_capacity(n);
+        for (i, p) in params.iter().enumerate() {
+            let cur = keys[i];
+            let prev = keys[if i == 0 { 0 } else { i - 1 }];
+            let next = keys[if i + 1 == n { i } else { i + 1 }];
+
+            // g_prev points back along the incoming segment, g_next forward
+            let g_prev = log_between(cur, prev);
+            let g_next = log_between(cur, next);
+            let back = vec3_scale(g_prev, -one);
+
+            let k = (one - p.tension) * half;
+            let source = vec3_add(
+                vec3_scale(back, k * (one + p.continuity) * (one + p.bias)),
+                vec3_scale(g_next, k * (one - p.continuity) * (one - p.bias))
+            );
+            let dest = vec3_add(
+                vec3_scale(back, k * (one - p.continuity) * (one + p.bias)),
+                vec3_scale(g_next, k * (one + p.continuity) * (one - p.bias))
+            );
+
+            let out = vec3_scale(vec3_add(source, vec3_scale(g_next, -one)), half);
+            let inc = vec3_scale(vec3_add(dest, g_prev), -half);
+            outgoing.push(mul(cur, *UnitQuaternion::exp(out)));
+            incoming.push(mul(cur, *UnitQuaternion::exp(inc)));
+        }
+
+        SquadSpline { keys, outgoing, incoming }
+    }
+
+    /// Number of segments, one less than the number of keyframes
+    #[inline(always)]
+    pub fn segments(&self) -> usize {
+        self.keys.len().saturating_sub(1)
+    }
+
+    /// Evaluates segment `segment` at `t` in `[0, 1]`
+    ///
+    /// Panics if `segment` is not less than `segments()`.
+    pub fn sample(&self, segment: usize, t: T) -> Quaternion<T> {
+        assert!(segment < self.segments(), "segment {} out of range", segment);
+
+        squad(self.keys[segment], self.keys[segment + 1],
+              self.outgoing[segment], self.incoming[segment + 1], t)
+    }
+}
+
+/// Cubic Bézier spline through a sequence of keyframes
+///
+/// Control points follow Shoemake's construction, giving a C1 curve.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BezierSpline<T> {
+    keys: Vec<Quaternion<T>>,
+    outgoing: Vec<Quaternion<T>>,
+    incoming: Vec<Quaternion<T>>,
+}
+
+impl<T> BezierSpline<T>
+    where T: Float
+{
+    /// Builds a spline, computing the control points automatically
+    pub fn new(keys: &[Quaternion<T>]) -> BezierSpline<T> {
+        // Reflects p through q on the sphere
+        fn double<T: Float>(p: Quaternion<T>, q: Quaternion<T>) -> Quaternion<T> {
+            let two = T::one() + T::one();
+            q * (two * dot(p, q)) - p
+        }
+
+        let keys = align_hemispheres(keys);
+        let n = keys.len();
+        let third = T::from_f64(1.0 / 3.0);
+
+        let mut outgoing = Vec::with_capacity(n);
+        let mut incoming = Vec::with_capacity(n);
+        for i in 0..n {
+            let cur = keys[i];
+            let prev = keys[if i == 0 { 0 } else { i - 1 }];
+            let next = keys[if i + 1 == n { i } else { i + 1 }];
+
+            // Shoemake's a_n = bisect(double(q_n-1, q_n), q_n+1), a third of
+            // the way out so the tangents match a Catmull–Rom curve
+            let a = normalize(double(prev, cur) + next);
+            let a = slerp_unclamped(cur, a, third);
+            outgoing.push(a);
+            incoming.push(double(a, cur));
+        }
+
+        BezierSpline { keys, outgoing, incoming }
+    }
+
+    /// Number of segments, one less than the number of keyframes
+    #[inline(always)]
+    pub fn segments(&self) -> usize {
+        self.keys.len().saturating_sub(1)
+    }
+
+    /// Evaluates segment `segment` at `t` in `[0, 1]`
+    ///
+    /// Panics if `segment` is not less than `segments()`.
+    pub fn sample(&self, segment: usize, t: T) -> Quaternion<T> {
+        assert!(segment < self.segments(), "segment {} out of range", segment);
+
+        bezier(&[self.keys[segment], self.outgoing[segment],
+                 self.incoming[segment + 1], self.keys[segment + 1]], t)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {axis_angle, id, square_len, slerp};
+
+    static EPSILON: f64 = 0.000001;
+
+    fn about_z(theta: f64) -> Quaternion<f64> {
+        axis_angle([0.0, 0.0, 1.0], theta)
+    }
+
+    fn keys() -> Vec<Quaternion<f64>> {
+        vec![
+            id(),
+            axis_angle([1.0, 0.0, 0.0], 0.8),
+            mul(axis_angle([0.0, 1.0, 0.0], 1.2), axis_angle([1.0, 0.0, 0.0], 0.8)),
+            axis_angle([0.0, 0.0, 1.0], -0.5),
+        ]
+    }
+
+    fn close(a: Quaternion<f64>, b: Quaternion<f64>, eps: f64) -> bool {
+        square_len(a - b) < eps * eps || square_len(a + b) < eps * eps
+    }
+
+    #[test]
+    fn test_squad_passes_through_keys() {
+        let keys = keys();
+        let spline = SquadSpline::new(&keys);
+        assert_eq!(spline.segments(), 3);
+        for i in 0..3 {
+            assert!(close(spline.sample(i, 0.0), keys[i], EPSILON));
+            assert!(close(spline.sample(i, 1.0), keys[i + 1], EPSILON));
+        }
+    }
+
+    #[test]
+    fn test_squad_uniform_rotation() {
+        // evenly spaced keys about one axis give constant angular velocity
+        let keys: Vec<_> = (0..4).map(|i| about_z(0.5 * i as f64)).collect();
+        let spline = SquadSpline::new(&keys);
+        assert!(close(spline.sample(1, 0.5), about_z(0.75), EPSILON));
+    }
+
+    #[test]
+    fn test_squad_c1_at_keys() {
+        let spline = SquadSpline::new(&keys());
+        let h = 1e-4;
+        for i in 0..2 {
+            let before = spline.sample(i, 1.0 - h);
+            let at = spline.sample(i + 1, 0.0);
+            let after = spline.sample(i + 1, h);
+            assert!(square_len((after - at) - (at - before)) < 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_bezier_two_points_is_slerp() {
+        let a = about_z(0.2);
+        let b = axis_angle([0.0, 1.0, 0.0], 1.1);
+        assert!(close(bezier(&[a, b], 0.3), slerp(a, b, 0.3), EPSILON));
+    }
+
+    #[test]
+    fn test_bezier_spline() {
+        let keys = keys();
+        let spline = BezierSpline::new(&keys);
+        let h = 1e-4;
+        for i in 0..3 {
+            assert!(close(spline.sample(i, 0.0), keys[i], EPSILON));
+            assert!(close(spline.sample(i, 1.0), keys[i + 1], EPSILON));
+            assert!((square_len(spline.sample(i, 0.4)) - 1.0).abs() < EPSILON);
+        }
+        for i in 0..2 {
+            let before = spline.sample(i, 1.0 - h);
+            let at = spline.sample(i + 1, 0.0);
+            let after = spline.sample(i + 1, h);
+            assert!(square_len((after - at) - (at - before)) < 1e-10);
+        }
+    }
+
+    #[test]
+    fn test_kochanek_bartels_defaults_to_squad() {
+        let keys = keys();
+        let params = vec![Tcb::default(); keys.len()];
+        let tcb = SquadSpline::kochanek_bartels(&keys, &params);
+        let squad = SquadSpline::new(&keys);
+        for i in 0..3 {
+            assert!(close(tcb.sample(i, 0.3), squad.sample(i, 0.3), EPSILON));
+        }
+    }
+
+    #[test]
+    fn test_kochanek_bartels_full_tension() {
+        // with tension 1 the tangents vanish and the segments slow to a stop at keys
+        let keys: Vec<_> = (0..3).map(|i| about_z(0.5 * i as f64)).collect();
+        let params = vec![Tcb { tension: 1.0, continuity: 0.0, bias: 0.0 }; 3];
+        let spline = SquadSpline::kochanek_bartels(&keys, &params);
+        assert!(close(spline.sample(0, 1.0), keys[1], EPSILON));
+        assert!(close(spline.sample(0, 0.5), about_z(0.25), EPSILON));
+        let h = 1e-3;
+        let step = square_len(spline.sample(1, h) - keys[1]).sqrt();
+        assert!(step < 0.25 * h);
+    }
+}
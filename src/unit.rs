@@ -0,0 +1,308 @@
+//! Unit quaternions representing rotations
+
+use vecmath::traits::Float;
+use std::fmt::Debug;
+use std::ops::{Deref, Mul, MulAssign, Neg};
+
+use {Quaternion, id, conj, mul, square_len, len, try_normalize, axis_angle, sinc};
+
+
+/// Tolerance on the squared length for a quaternion to count as unit length
+#[inline(always)]
+fn tolerance<T>() -> T
+    where T: Float
+{
+    T::from_f64(1e-5)
+}
+
+/// Quaternion guaranteed to have unit length
+///
+/// The inner quaternion can be read through `Deref` but only modified through
+/// operations that preserve the invariant.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnitQuaternion<T>(Quaternion<T>);
+
+impl<T> UnitQuaternion<T>
+    where T: Float
+{
+    /// The identity rotation
+    #[inline(always)]
+    pub fn identity() -> UnitQuaternion<T> {
+        UnitQuaternion(id())
+    }
+
+    /// Normalizes `q`, returning `None` if it has zero length
+    #[inline(always)]
+    pub fn new(q: Quaternion<T>) -> Option<UnitQuaternion<T>> {
+        try_normalize(q, T::zero()).map(UnitQuaternion)
+    }
+
+    /// Wraps `q` without rescaling, returning `None` if it is not unit length
+    #[inline(always)]
+    pub fn from_unit(q: Quaternion<T>) -> Option<UnitQuaternion<T>> {
+        let drift = square_len(q) - T::one();
+        if drift < tolerance() && -drift < tolerance() {
+            Some(UnitQuaternion(q))
+        } else {
+            None
+        }
+    }
+
+    /// Wraps `q` assuming it already has unit length
+    #[inline(always)]
+    pub fn new_unchecked(q: Quaternion<T>) -> UnitQuaternion<T> {
+        UnitQuaternion(q)
+    }
+
+    /// Constructs the rotation of `theta` radians about `axis`
+    ///
+    /// The axis does not need to be normalized. A zero axis gives the identity.
+    #[inline(always)]
+    pub fn from_axis_angle(axis: [T; 3], theta: T) -> UnitQuaternion<T>
+        where T: Debug
+    {
+        use vecmath::{vec3_len, vec3_scale};
+
+        let l = vec3_len(axis);
+        if l > T::zero() {
+            UnitQuaternion(axis_angle(vec3_scale(axis, T::one() / l), theta))
+        } else {
+            UnitQuaternion::identity()
+        }
+    }
+
+    /// Constructs the shortest rotation taking the direction of `a` to that of `b`
+    #[inline(always)]
+    pub fn rotation_from_to(a: [T; 3], b: [T; 3]) -> UnitQuaternion<T>
+        where T: Debug
+    {
+        ::rotation_from_to(a, b)
+    }
+
+    /// Returns the underlying quaternion
+    #[inline(always)]
+    pub fn into_inner(self) -> Quaternion<T> {
+        self.0
+    }
+
+    /// Inverse rotation, which for a unit quaternion is its conjugate
+    #[inline(always)]
+    pub fn inverse(self) -> UnitQuaternion<T> {
+        UnitQuaternion(conj(self.0))
+    }
+
+    /// Rotates the given vector
+    #[inline(always)]
+    pub fn rotate_vector(self, v: [T; 3]) -> [T; 3] {
+        ::rotate_vector(self, v)
+    }
+
+    /// Rotation angle in `[0, pi]`
+    #[inline(always)]
+    pub fn angle(self) -> T {
+        ::to_axis_angle(self).1
+    }
+
+    /// Unit rotation axis, the x axis for the identity
+    #[inline(always)]
+    pub fn axis(self) -> [T; 3] {
+        ::to_axis_angle(self).0
+    }
+
+    /// Constructs the rotation described by a rotation vector
+    #[inline(always)]
+    pub fn from_rotation_vector(r: [T; 3]) -> UnitQuaternion<T> {
+        ::from_rotation_vector(r)
+    }
+
+    /// Rotation vector, with direction the axis and norm the angle in `[0, pi]`
+    #[inline(always)]
+    pub fn to_rotation_vector(self) -> [T; 3] {
+        ::to_rotation_vector(self)
+    }
+
+    /// Spherical linear interpolation towards `other`, with `t` clamped to `[0, 1]`
+    #[inline(always)]
+    pub fn slerp(self, other: UnitQuaternion<T>, t: T) -> UnitQuaternion<T> {
+        UnitQuaternion::renormalized(::slerp(self.0, other.0, t))
+    }
+
+    /// Normalized linear interpolation towards `other`
+    #[inline(always)]
+    pub fn nlerp(self, other: UnitQuaternion<T>, t: T) -> UnitQuaternion<T> {
+        UnitQuaternion(::nlerp(self.0, other.0, t))
+    }
+
+    /// Exponential of the pure quaternion `(0, v)`, a rotation of `2 * |v|` about `v`
+    #[inline(always)]
+    pub fn exp(v: [T; 3]) -> UnitQuaternion<T> {
+        use vecmath::{vec3_len, vec3_scale};
+
+        let theta = vec3_len(v);
+        UnitQuaternion((theta.cos(), vec3_scale(v, sinc(theta))).into())
+    }
+
+    /// Logarithm, the vector part of a pure quaternion
+    ///
+    /// The result has length in `[0, pi]`, half the rotation angle.
+    #[inline(always)]
+    pub fn ln(self) -> [T; 3] {
+        use vecmath::{vec3_len, vec3_scale};
+
+        let v = self.0.vector();
+        let v_len = vec3_len(v);
+        if v_len > T::zero() {
+            vec3_scale(v, v_len.atan2(self.0.w) / v_len)
+        } else {
+            [T::zero(); 3]
+        }
+    }
+
+    /// Scales the rotation angle by `t`
+    #[inline(always)]
+    pub fn pow(self, t: T) -> UnitQuaternion<T> {
+        use vecmath::vec3_scale;
+
+        UnitQuaternion::exp(vec3_scale(self.ln(), t))
+    }
+
+    /// Rotation by half the angle about the same axis
+    #[inline(always)]
+    pub fn sqrt(self) -> UnitQuaternion<T> {
+        self.pow(T::from_f64(0.5))
+    }
+
+    /// Rescales to unit length if rounding has let the length drift
+    #[inline(always)]
+    fn renormalized(q: Quaternion<T>) -> UnitQuaternion<T> {
+        let drift = square_len(q) - T::one();
+        if drift < tolerance() && -drift < tolerance() {
+            UnitQuaternion(q)
+        } else {
+            UnitQuaternion(q / len(q))
+        }
+    }
+}
+
+impl<T> Deref for UnitQuaternion<T> {
+    type Target = Quaternion<T>;
+
+    #[inline(always)]
+    fn deref(&self) -> &Quaternion<T> {
+        &self.0
+    }
+}
+
+impl<T> From<UnitQuaternion<T>> for Quaternion<T> {
+    #[inline(always)]
+    fn from(q: UnitQuaternion<T>) -> Quaternion<T> {
+        q.0
+    }
+}
+
+impl<T> Mul for UnitQuaternion<T>
+    where T: Float
+{
+    type Output = UnitQuaternion<T>;
+
+    /// Composes two rotations, renormalizing once rounding error accumulates
+    #[inline(always)]
+    fn mul(self, b: UnitQuaternion<T>) -> UnitQuaternion<T> {
+        UnitQuaternion::renormalized(mul(self.0, b.0))
+    }
+}
+
+impl<T> MulAssign for UnitQuaternion<T>
+    where T: Float
+{
+    #[inline(always)]
+    fn mul_assign(&mut self, b: UnitQuaternion<T>) {
+        *self = *self * b;
+    }
+}
+
+impl<T> Neg for UnitQuaternion<T>
+    where T: Float
+{
+    type Output = UnitQuaternion<T>;
+
+    /// Negates all components, which represents the same rotation
+    #[inline(always)]
+    fn neg(self) -> UnitQuaternion<T> {
+        UnitQuaternion(-self.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    static EPSILON: f64 = 0.000001;
+
+    #[test]
+    fn test_new() {
+        let q = UnitQuaternion::new(Quaternion::new(2.0, 0.0, 0.0, 0.0)).unwrap();
+        assert_eq!(q.into_inner(), id());
+        assert!(UnitQuaternion::new(Quaternion::new(0.0, 0.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_from_unit() {
+        assert!(UnitQuaternion::from_unit(Quaternion::new(0.0, 1.0, 0.0, 0.0)).is_some());
+        assert!(UnitQuaternion::from_unit(Quaternion::new(1.0, 1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn test_from_axis_angle_unnormalized() {
+        let q = UnitQuaternion::from_axis_angle([0.0, 0.0, 3.0], PI / 2.0);
+        assert!((square_len(*q) - 1.0).abs() < EPSILON);
+        let v = q.rotate_vector([1.0, 0.0, 0.0]);
+        assert!(v[0].abs() < EPSILON);
+        assert!((v[1] - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_axis_angle_accessors() {
+        let q: UnitQuaternion<f64> = UnitQuaternion::from_axis_angle([2.0, 0.0, 0.0], 0.9);
+        assert!((q.angle() - 0.9).abs() < EPSILON);
+        assert_eq!(q.axis(), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let q: UnitQuaternion<f64> = UnitQuaternion::from_axis_angle([1.0, 2.0, 3.0], 0.7);
+        let r = q * q.inverse();
+        assert!((r.w - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn test_exp_ln() {
+        let q: UnitQuaternion<f64> = UnitQuaternion::from_axis_angle([1.0, -1.0, 2.0], 2.5);
+        let r = UnitQuaternion::exp(q.ln());
+        assert!(square_len(*r - *q) < EPSILON);
+        assert_eq!(UnitQuaternion::<f64>::identity().ln(), [0.0; 3]);
+    }
+
+    #[test]
+    fn test_pow_sqrt() {
+        let q: UnitQuaternion<f64> = UnitQuaternion::from_axis_angle([0.0, 1.0, 0.0], 1.5);
+        let third = UnitQuaternion::from_axis_angle([0.0, 1.0, 0.0], 0.5);
+        assert!(square_len(*q.pow(1.0 / 3.0) - *third) < EPSILON);
+
+        let r = q.sqrt();
+        assert!(square_len(*(r * r) - *q) < EPSILON);
+    }
+
+    #[test]
+    fn test_composition_stays_unit() {
+        let step: UnitQuaternion<f32> = UnitQuaternion::from_axis_angle([1.0, -2.0, 0.5], 0.01);
+        let mut q = UnitQuaternion::identity();
+        for _ in 0..100000 {
+            q *= step;
+        }
+        assert!((square_len(*q) - 1.0).abs() < tolerance());
+    }
+}
@@ -0,0 +1,250 @@
+//! Solutions to Wahba's problem
+//!
+//! Given unit vectors `from[i]` and `to[i]` with weights `w[i]`, find the
+//! rotation `q` minimizing `sum(w[i] * |to[i] - rotate_vector(q, from[i])|^2)`.
+//! With body frame observations as `from` and reference directions as `to`,
+//! `q` maps body coordinates to the reference frame, as in `mekf` and `ahrs`.
+//! For a single pair this is what `rotation_from_to` solves.
+
+use vecmath::traits::Float;
+use vecmath::{Matrix3, mat3_det, vec3_cross, vec3_dot, vec3_normalized,
+              vec3_square_len};
+
+use {Quaternion, UnitQuaternion, conj, from_mat3, normalize};
+use eigen::{davenport_k, dominant_eigenvector};
+
+
+/// Attitude profile matrix `B = sum(w[i] * to[i] * from[i]^T)`
+#[allow(clippy::needless_range_loop)]
+fn profile<T>(from: &[[T; 3]], to: &[[T; 3]], weights: &[T]) -> Matrix3<T>
+    where T: Float
+{
+    assert!(from.len() == to.len() && to.len() == weights.len(),
+            "from, to and weights must have the same length");
+
+    let mut b = [[T::zero(); 3]; 3];
+    for n in 0..from.len() {
+        let f = vec3_normalized(from[n]);
+        let t = vec3_normalized(to[n]);
+        for i in 0..3 {
+            for j in 0..3 {
+                b[i][j] += weights[n] * t[i] * f[j];
+            }
+        }
+    }
+    b
+}
+
+/// TRIAD solution from two vector pairs
+///
+/// The first pair is matched exactly and the second only fixes the rotation
+/// about it, so the more accurate pair should come first. Returns `None` if
+/// either pair of vectors is parallel.
+pub fn triad<T>(from: [[T; 3]; 2], to: [[T; 3]; 2]) -> Option<UnitQuaternion<T>>
+    where T: Float
+{
+    fn frame<T: Float>(a: [T; 3], b: [T; 3]) -> Option<Matrix3<T>> {
+        let t1 = vec3_normalized(a);
+        let c = vec3_cross(t1, b);
+        if vec3_square_len(c) <= T::from_f64(1e-24) {
+            return None;
+        }
+        let t2 = vec3_normalized(c);
+        Some([t1, t2, vec3_cross(t1, t2)])
+    }
+
+    let f = frame(from[0], from[1])?;
+    let t = frame(to[0], to[1])?;
+
+    // R = [t1 t2 t3] * [f1 f2 f3]^T, with the triads stored as rows
+    let mut r = [[T::zero(); 3]; 3];
+    for (i, row) in r.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = t[0][i] * f[0][j] + t[1][i] * f[1][j] + t[2][i] * f[2][j];
+        }
+    }
+    Some(from_mat3(r))
+}
+
+/// Davenport's q-method, the dominant eigenvector of the `K` matrix
+///
+/// Panics if the slices differ in length.
+pub fn davenport<T>(from: &[[T; 3]], to: &[[T; 3]], weights: &[T]) -> UnitQuaternion<T>
+    where T: Float
+{
+    let k = davenport_k(profile(from, to, weights));
+    let (_, q) = dominant_eigenvector(k);
+    UnitQuaternion::new_unchecked(normalize(q))
+}
+
+/// Unnormalized QUEST quaternion for profile matrix `b`, refining the
+/// largest eigenvalue of `K` from `lambda` by Newton's method on its
+/// characteristic polynomial
+#[allow(clippy::needless_range_loop)]
+fn quest_solve<T>(b: Matrix3<T>, lambda: T) -> Quaternion<T>
+    where T: Float
+{
+    let zero = T::zero();
+    let two = T::one() + T::one();
+
+    let sigma = b[0][0] + b[1][1] + b[2][2];
+    let z = [b[2][1] - b[1][2], b[0][2] - b[2][0], b[1][0] - b[0][1]];
+    let mut s = [[zero; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            s[i][j] = b[i][j] + b[j][i];
+        }
+    }
+    let mul_vec = |m: &Matrix3<T>, v: [T; 3]| [
+        vec3_dot(m[0], v), vec3_dot(m[1], v), vec3_dot(m[2], v)
+    ];
+    let sz = mul_vec(&s, z);
+    let s2z = mul_vec(&s, sz);
+
+    // trace of the adjugate of S, and its determinant
+    let kappa = s[1][1] * s[2][2] - s[1][2] * s[2][1]
+        + s[0][0] * s[2][2] - s[0][2] * s[2][0]
+        + s[0][0] * s[1][1] - s[0][1] * s[1][0];
+    let delta = mat3_det(s);
+
+    let a = sigma * sigma - kappa;
+    let bb = sigma * sigma + vec3_dot(z, z);
+    let c = delta + vec3_dot(z, sz);
+    let d = vec3_dot(z, s2z);
+
+    let mut lambda = lambda;
+    for _ in 0..10 {
+        let l2 = lambda * lambda;
+        let f = l2 * l2 - (a + bb) * l2 - c * lambda + (a * bb + c * sigma - d);
+        let df = T::from_f64(4.0) * l2 * lambda - two * (a + bb) * lambda - c;
+        if df == zero {
+            break;
+        }
+        let step = f / df;
+        lambda -= step;
+        if step * step <= T::from_f64(1e-28) * lambda * lambda {
+            break;
+        }
+    }
+
+    let alpha = lambda * lambda - sigma * sigma + kappa;
+    let beta = lambda - sigma;
+    let gamma = (lambda + sigma) * alpha - delta;
+    Quaternion::new(
+        gamma,
+        alpha * z[0] + beta * sz[0] + s2z[0],
+        alpha * z[1] + beta * sz[1] + s2z[1],
+        alpha * z[2] + beta * sz[2] + s2z[2],
+    )
+}
+
+/// QUEST, Shuster's quaternion estimator
+///
+/// Finds the largest eigenvalue of `K` by Newton's method starting from the
+/// sum of the weights, then solves for its eigenvector directly, which is
+/// much cheaper than `davenport`. The direct solve breaks down for rotations
+/// near a half turn, so the reference frame is then turned a half turn about
+/// one of its axes first (Shuster's method of sequential rotations). Panics
+/// if the slices differ in length.
+pub fn quest<T>(from: &[[T; 3]], to: &[[T; 3]], weights: &[T]) -> UnitQuaternion<T>
+    where T: Float
+{
+    let zero = T::zero();
+    let one = T::one();
+    let b = profile(from, to, weights);
+    let lambda = weights.iter().fold(zero, |acc, &w| acc + w);
+    let scale = lambda * lambda * lambda;
+
+    // at least one of the four frames leaves |w| >= 1/2
+    for axis in 0..4 {
+        let mut b = b;
+        let mut turn = Quaternion::new(one, zero, zero, zero);
+        if axis > 0 {
+            for (i, row) in b.iter_mut().enumerate() {
+                if i + 1 != axis {
+                    for x in row.iter_mut() {
+                        *x = -*x;
+                    }
+                }
+            }
+            turn = Quaternion::new(zero, zero, zero, zero);
+            turn[axis] = one;
+        }
+
+        let q = quest_solve(b, lambda);
+        let norm2 = ::square_len(q);
+        if norm2 > T::from_f64(1e-20) * scale * scale && q.w * q.w >= T::from_f64(0.1) * norm2 {
+            let q = UnitQuaternion::new_unchecked(normalize(q));
+            return UnitQuaternion::new_unchecked(conj(turn)) * q;
+        }
+    }
+
+    // only reached for degenerate observations, such as all zero weights
+    davenport(from, to, weights)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rotate_vector;
+    use test_util::{assert_same_rotation, rot};
+
+    fn observations(q: UnitQuaternion<f64>) -> (Vec<[f64; 3]>, Vec<[f64; 3]>) {
+        let from = vec![[1.0, 0.0, 0.0], [0.0, 0.6, 0.8], [-0.48, 0.6, 0.64]];
+        let to = from.iter().map(|&v| rotate_vector(q, v)).collect();
+        (from, to)
+    }
+
+    #[test]
+    fn test_exact_observations() {
+        let truth = rot([0.4, -1.0, 0.2], 2.1);
+        let (from, to) = observations(truth);
+        let weights = [1.0, 2.0, 0.5];
+
+        assert_same_rotation(davenport(&from, &to, &weights), truth, 1e-12);
+        assert_same_rotation(quest(&from, &to, &weights), truth, 1e-12);
+        let t = triad([from[0], from[1]], [to[0], to[1]]).unwrap();
+        assert_same_rotation(t, truth, 1e-12);
+    }
+
+    #[test]
+    fn test_noisy_observations() {
+        let truth = rot([1.0, 1.0, 1.0], 0.7);
+        let (from, mut to) = observations(truth);
+        to[0][1] += 0.01;
+        to[1][2] -= 0.02;
+        to[2][0] += 0.015;
+        let weights = [1.0, 1.0, 1.0];
+
+        let d = davenport(&from, &to, &weights);
+        let q = quest(&from, &to, &weights);
+        assert_same_rotation(d, q, 1e-12);
+        // the readings are off by up to 0.02, which the estimate inherits
+        assert_same_rotation(d, truth, 0.02);
+    }
+
+    #[test]
+    fn test_half_turn() {
+        let truth = rot([0.0, 0.0, 1.0], ::std::f64::consts::PI);
+        let (from, to) = observations(truth);
+        let weights = [1.0, 1.0, 1.0];
+        assert_same_rotation(quest(&from, &to, &weights), truth, 1e-12);
+    }
+
+    #[test]
+    fn test_single_pair() {
+        // one pair constrains the rotation like rotation_from_to
+        let from = [[1.0, 2.0, 0.0]];
+        let to = [[0.0, 0.0, 3.0]];
+        let q = davenport(&from, &to, &[1.0]);
+        let v: [f64; 3] = rotate_vector(q, vec3_normalized(from[0]));
+        assert!((v[2] - 1.0).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_triad_parallel() {
+        let v = [0.0, 1.0, 0.0];
+        assert_eq!(triad([v, v], [v, [1.0, 0.0, 0.0]]), None);
+    }
+}